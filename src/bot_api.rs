@@ -0,0 +1,110 @@
+//! Calls to the Telegram Bot API which the telegram-bot crate does not provide yet
+
+use config;
+use http_client;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use hyper::Client;
+use hyper;
+use hyper::header::ContentType;
+use rustc_serialize::json::{self, Json};
+use rustc_serialize::Encodable;
+use telegram_bot::Error;
+
+/// Separates the parts of a multipart/form-data request, must not occur in the parts
+const BOUNDARY :&'static str = "coredumpbot-form-boundary-8d1a52c6";
+
+lazy_static! {
+  /// Shared by the outbox and the webhook registration, a hanging request must not stall the outbox
+  static ref CLIENT :Client = http_client::with_timeouts();
+}
+
+#[derive(RustcEncodable)]
+struct SendVenue<'a> {
+  chat_id :i64,
+  latitude :f64,
+  longitude :f64,
+  title :&'a str,
+  address :&'a str,
+}
+
+/// Send a location together with a name and an address, see https://core.telegram.org/bots/api#sendvenue
+pub fn send_venue(chat_id :i64, latitude :f64, longitude :f64, title :&str, address :&str) -> Result<Json, Error> {
+  call("sendVenue", &SendVenue{
+    chat_id: chat_id,
+    latitude: latitude,
+    longitude: longitude,
+    title: title,
+    address: address,
+  })
+}
+
+/// Register the webhook and upload the certificate of its server, see https://core.telegram.org/bots/api#setwebhook.
+/// Telegram only accepts a self-signed certificate if it is uploaded.
+pub fn set_webhook_with_certificate(url :&str, certificate :&Path) -> Result<Json, Error> {
+  let mut pem = vec![];
+  try!(File::open(certificate).and_then(|mut f| f.read_to_end(&mut pem)).map_err(Error::Io));
+
+  let content_type = try!(format!("multipart/form-data; boundary={}", BOUNDARY).parse()
+    .map_err(|_| Error::InvalidState("invalid multipart content type".into())));
+  post("setWebhook", ContentType(content_type), &form(url, &pem))
+}
+
+/// The form of setWebhook with the `url` and the `certificate` as file
+fn form(url :&str, pem :&[u8]) -> Vec<u8> {
+  let mut body = format!("--{b}\r\nContent-Disposition: form-data; name=\"url\"\r\n\r\n{url}\r\n\
+    --{b}\r\nContent-Disposition: form-data; name=\"certificate\"; filename=\"certificate.pem\"\r\n\
+    Content-Type: application/x-pem-file\r\n\r\n", b=BOUNDARY, url=url).into_bytes();
+  body.extend_from_slice(pem);
+  body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+  body
+}
+
+/// POST the parameters as JSON and return the `result` of the response
+fn call<T: Encodable>(method :&str, parameters :&T) -> Result<Json, Error> {
+  let body = try!(json::encode(parameters).map_err(Error::JsonEncode));
+  post(method, ContentType::json(), body.as_bytes())
+}
+
+/// The errors are those of the telegram-bot crate, so the `Outbox` treats them alike
+fn post(method :&str, content_type :ContentType, body :&[u8]) -> Result<Json, Error> {
+  let token = try!(config::get().token.clone().ok_or(Error::InvalidState("no Telegram token configured".into())));
+
+  let mut res = try!(CLIENT.post(&*format!("https://api.telegram.org/bot{}/{}", token, method))
+    .header(content_type)
+    .body(body)
+    .send()
+    .map_err(Error::Http));
+
+  let mut response = String::new();
+  // Like a failed request, the connection broke
+  try!(res.read_to_string(&mut response).map_err(|e| Error::Http(hyper::Error::Io(e))));
+
+  let response = try!(Json::from_str(&response)
+    .map_err(|e| Error::InvalidState(format!("{}: unable to parse response: {}", method, e))));
+  match response.find("ok").and_then(|ok| ok.as_boolean()) {
+    Some(true) => Ok(response.find("result").cloned().unwrap_or(Json::Null)),
+    _ => {
+      let description = response.find("description").and_then(|d| d.as_string()).unwrap_or("no description");
+      Err(Error::Api(format!("{}: {}", method, description)))
+    },
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::form;
+
+  #[test]
+  fn webhook_form() {
+    let body = String::from_utf8(form("https://bot.example.com/secret", b"-----BEGIN CERTIFICATE-----")).unwrap();
+    assert_eq!("--coredumpbot-form-boundary-8d1a52c6\r\nContent-Disposition: form-data; name=\"url\"\r\n\r\n\
+      https://bot.example.com/secret\r\n--coredumpbot-form-boundary-8d1a52c6\r\n\
+      Content-Disposition: form-data; name=\"certificate\"; filename=\"certificate.pem\"\r\n\
+      Content-Type: application/x-pem-file\r\n\r\n-----BEGIN CERTIFICATE-----\r\n\
+      --coredumpbot-form-boundary-8d1a52c6--\r\n", body);
+  }
+}
@@ -0,0 +1,509 @@
+//! Parse iCalendar feeds and expand recurring events
+//!
+//! Supports the subset of RFC 5545 calendars usually contain:
+//! `DTSTART` as UTC, floating or with a `TZID`, all day events, `RRULE` with
+//! `FREQ`, `INTERVAL`, `COUNT`, `UNTIL` and `BYDAY`, `EXDATE` and overridden occurrences.
+//! Every `TZID` is assumed to be the timezone of the space.
+
+use chrono::{DateTime, UTC, NaiveDate, NaiveDateTime, Duration, Datelike, Weekday, TimeZone};
+use local_time;
+
+use std::cmp;
+
+/// Upper limit of occurrences of an event within the requested period
+const MAX_OCCURRENCES :usize = 1000;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Frequency {
+  Daily,
+  Weekly,
+  Monthly,
+  Yearly,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+  frequency :Frequency,
+  interval :u32,
+  count :Option<u32>,
+  until :Option<NaiveDateTime>,
+  /// Ordinal (0 for every) and weekday, e.g. `1MO` for the first Monday of the month
+  by_day :Vec<(i32, Weekday)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+  pub uid :String,
+  pub summary :String,
+  pub location :Option<String>,
+  /// Wall clock time of the space
+  start :NaiveDateTime,
+  all_day :bool,
+  rule :Option<Rule>,
+  exceptions :Vec<NaiveDateTime>,
+  recurrence_id :Option<NaiveDateTime>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Occurrence {
+  pub uid :String,
+  pub summary :String,
+  pub location :Option<String>,
+  pub start :DateTime<UTC>,
+  pub all_day :bool,
+}
+
+impl Occurrence {
+  /// Identifies a single occurrence of a recurring event
+  pub fn key(&self) -> String {
+    format!("{}@{}", self.uid, self.start.timestamp())
+  }
+
+  /// One line like "Mon 24.10. 20:00 Hackabend (Coredump)" in the space's timezone
+  pub fn describe(&self) -> String {
+    let start = local_time::to_local(&self.start);
+    let when = if self.all_day {
+      start.format("%a %d.%m.").to_string()
+    } else {
+      start.format("%a %d.%m. %H:%M").to_string()
+    };
+
+    match self.location {
+      Some(ref location) => format!("{} {} ({})", when, self.summary, location),
+      None => format!("{} {}", when, self.summary),
+    }
+  }
+}
+
+/// Parse all `VEVENT`s of an iCalendar document
+pub fn parse_calendar(ics :&str) -> Vec<Event> {
+  let mut events :Vec<Event> = vec![];
+  let mut current :Option<Event> = None;
+
+  for line in unfold(ics) {
+    let (name, value) = match split_property(&line) {
+      Some(p) => p,
+      None => continue,
+    };
+
+    if name == "BEGIN" && value == "VEVENT" {
+      current = Some( Event{
+        uid: String::new(),
+        summary: String::new(),
+        location: None,
+        start: NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 0),
+        all_day: false,
+        rule: None,
+        exceptions: vec![],
+        recurrence_id: None,
+      });
+      continue;
+    }
+    if name == "END" && value == "VEVENT" {
+      if let Some(event) = current.take() {
+        events.push(event);
+      }
+      continue;
+    }
+
+    let event = match current {
+      Some(ref mut event) => event,
+      None => continue,
+    };
+
+    match &*name {
+      "UID" => event.uid = value.into(),
+      "SUMMARY" => event.summary = unescape(value),
+      "LOCATION" => event.location = Some(unescape(value)),
+      "DTSTART" => if let Some((start, all_day)) = parse_date_time(value) {
+        event.start = start;
+        event.all_day = all_day;
+      },
+      "RRULE" => event.rule = parse_rule(value),
+      "EXDATE" => for date in value.split(',') {
+        if let Some((exception, _)) = parse_date_time(date) {
+          event.exceptions.push(exception);
+        }
+      },
+      "RECURRENCE-ID" => event.recurrence_id = parse_date_time(value).map(|(id, _)| id),
+      _ => {},
+    }
+  }
+
+  // An overridden occurrence replaces the one of the recurring event
+  let overrides :Vec<(String, NaiveDateTime)> = events.iter()
+    .filter_map(|e| e.recurrence_id.map(|id| (e.uid.clone(), id)))
+    .collect();
+  for (uid, id) in overrides {
+    for event in events.iter_mut().filter(|e| e.uid == uid && e.recurrence_id.is_none()) {
+      event.exceptions.push(id);
+    }
+  }
+
+  events
+}
+
+/// All occurrences overlapping [from, until), sorted by start
+pub fn occurrences(events :&[Event], from :&DateTime<UTC>, until :&DateTime<UTC>) -> Vec<Occurrence> {
+  // An all day event which started the day before may still be running
+  let local_from = local_time::to_local(from).naive_local() - Duration::days(1);
+  let local_until = local_time::to_local(until).naive_local();
+  let mut found = vec![];
+
+  for event in events {
+    for start in event.starts_between(&local_from, &local_until) {
+      let utc = local_time::from_local(&start);
+      let end = if event.all_day { utc + Duration::days(1) } else { utc };
+
+      if end >= *from && utc < *until {
+        found.push( Occurrence{
+          uid: event.uid.clone(),
+          summary: event.summary.clone(),
+          location: event.location.clone(),
+          start: utc,
+          all_day: event.all_day,
+        });
+      }
+    }
+  }
+
+  found.sort_by(|a, b| a.start.cmp(&b.start));
+  found
+}
+
+impl Event {
+  /// Local start times of the occurrences from `from` up to `until`.
+  /// Only `COUNT` limits the series, occurrences before `from` count for it as well.
+  fn starts_between(&self, from :&NaiveDateTime, until :&NaiveDateTime) -> Vec<NaiveDateTime> {
+    let rule = match self.rule {
+      Some(ref rule) => rule,
+      None => return if self.start >= *from && self.start <= *until { vec![self.start] } else { vec![] },
+    };
+
+    let mut starts = vec![];
+    let mut counted = 0;
+    // Every period is at least a day long, so `until` is passed by then even if some periods have no start
+    let periods = cmp::max(0, (*until - self.start).num_days()) / rule.interval as i64 + 2;
+
+    // The periods are expanded in order, so the first start after `until` ends the expansion
+    'periods: for period in 0..periods as u32 {
+      for start in rule.candidates(&self.start, period) {
+        if start < self.start {
+          continue;
+        }
+        if start > *until || rule.until.map_or(false, |u| start > u) {
+          break 'periods;
+        }
+
+        counted += 1;
+        if rule.count.map_or(false, |count| counted > count) || starts.len() >= MAX_OCCURRENCES {
+          break 'periods;
+        }
+        if start >= *from && !self.exceptions.contains(&start) {
+          starts.push(start);
+        }
+      }
+    }
+
+    starts
+  }
+}
+
+impl Rule {
+  /// Possible starts within the n-th period of the rule, sorted
+  fn candidates(&self, dtstart :&NaiveDateTime, period :u32) -> Vec<NaiveDateTime> {
+    let time = dtstart.time();
+    let step = period as i64 * self.interval as i64;
+
+    let mut dates = match self.frequency {
+      Frequency::Daily => vec![dtstart.date() + Duration::days(step)],
+      Frequency::Weekly => {
+        let week = dtstart.date() + Duration::days(7 * step - dtstart.weekday().num_days_from_monday() as i64);
+        if self.by_day.is_empty() {
+          vec![dtstart.date() + Duration::days(7 * step)]
+        } else {
+          self.by_day.iter().map(|&(_, day)| week + Duration::days(day.num_days_from_monday() as i64)).collect()
+        }
+      },
+      Frequency::Monthly => {
+        let months = dtstart.month0() as i64 + step;
+        let year = dtstart.year() + (months / 12) as i32;
+        let month = (months % 12) as u32 + 1;
+
+        if self.by_day.is_empty() {
+          NaiveDate::from_ymd_opt(year, month, dtstart.day()).into_iter().collect()
+        } else {
+          let mut dates = vec![];
+          for &(ordinal, day) in &self.by_day {
+            dates.extend(weekdays_in_month(year, month, day, ordinal));
+          }
+          dates
+        }
+      },
+      Frequency::Yearly => {
+        NaiveDate::from_ymd_opt(dtstart.year() + step as i32, dtstart.month(), dtstart.day()).into_iter().collect()
+      },
+    };
+
+    dates.sort();
+    dates.into_iter().map(|date| date.and_time(time)).collect()
+  }
+}
+
+/// Every `day` in the month for ordinal 0, otherwise the n-th (negative: from the end)
+fn weekdays_in_month(year :i32, month :u32, day :Weekday, ordinal :i32) -> Vec<NaiveDate> {
+  let mut all = vec![];
+  let mut date = NaiveDate::from_ymd(year, month, 1);
+  while date.month() == month {
+    if date.weekday() == day {
+      all.push(date);
+    }
+    date = date.succ();
+  }
+
+  if ordinal == 0 {
+    all
+  } else if ordinal > 0 {
+    all.into_iter().nth(ordinal as usize - 1).into_iter().collect()
+  } else {
+    all.into_iter().rev().nth((-ordinal) as usize - 1).into_iter().collect()
+  }
+}
+
+fn parse_rule(value :&str) -> Option<Rule> {
+  let mut rule = Rule{ frequency: Frequency::Daily, interval: 1, count: None, until: None, by_day: vec![] };
+  let mut frequency = None;
+
+  for part in value.split(';') {
+    let mut kv = part.splitn(2, '=');
+    let (key, value) = match (kv.next(), kv.next()) {
+      (Some(key), Some(value)) => (key, value),
+      _ => continue,
+    };
+
+    match key {
+      "FREQ" => frequency = match value {
+        "DAILY" => Some(Frequency::Daily),
+        "WEEKLY" => Some(Frequency::Weekly),
+        "MONTHLY" => Some(Frequency::Monthly),
+        "YEARLY" => Some(Frequency::Yearly),
+        _ => None,
+      },
+      "INTERVAL" => rule.interval = value.parse().unwrap_or(1),
+      "COUNT" => rule.count = value.parse().ok(),
+      "UNTIL" => rule.until = parse_date_time(value).map(|(until, _)| until),
+      "BYDAY" => rule.by_day = value.split(',').filter_map(parse_by_day).collect(),
+      _ => {},
+    }
+  }
+
+  if rule.interval == 0 {
+    rule.interval = 1;
+  }
+
+  frequency.map(|frequency| {
+    rule.frequency = frequency;
+    rule
+  })
+}
+
+/// "MO", "1MO" or "-1FR"
+fn parse_by_day(value :&str) -> Option<(i32, Weekday)> {
+  if value.len() < 2 {
+    return None;
+  }
+  let (ordinal, day) = value.split_at(value.len() - 2);
+  let ordinal = if ordinal.is_empty() || ordinal == "+" { 0 } else { match ordinal.parse() { Ok(o) => o, Err(_) => return None } };
+  let day = match day {
+    "MO" => Weekday::Mon,
+    "TU" => Weekday::Tue,
+    "WE" => Weekday::Wed,
+    "TH" => Weekday::Thu,
+    "FR" => Weekday::Fri,
+    "SA" => Weekday::Sat,
+    "SU" => Weekday::Sun,
+    _ => return None,
+  };
+
+  Some((ordinal, day))
+}
+
+/// Returns the wall clock time of the space and whether it is a date without time
+fn parse_date_time(value :&str) -> Option<(NaiveDateTime, bool)> {
+  let value = value.trim();
+
+  if value.len() == 8 {
+    NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(|date| (date.and_hms(0, 0, 0), true))
+  } else if value.ends_with('Z') {
+    NaiveDateTime::parse_from_str(&value[..value.len()-1], "%Y%m%dT%H%M%S").ok()
+      .map(|utc| (local_time::to_local(&UTC.from_utc_datetime(&utc)).naive_local(), false))
+  } else {
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok().map(|local| (local, false))
+  }
+}
+
+/// Lines starting with whitespace continue the previous line
+fn unfold(ics :&str) -> Vec<String> {
+  let mut lines :Vec<String> = vec![];
+
+  for line in ics.split('\n') {
+    let line = line.trim_right_matches('\r');
+    if line.starts_with(' ') || line.starts_with('\t') {
+      if let Some(last) = lines.last_mut() {
+        last.push_str(&line[1..]);
+        continue;
+      }
+    }
+    lines.push(line.into());
+  }
+
+  lines
+}
+
+/// Splits "NAME;PARAM=X:VALUE" into name and value, the parameters are ignored
+fn split_property(line :&str) -> Option<(String, &str)> {
+  let mut in_quotes = false;
+
+  for (i, c) in line.char_indices() {
+    match c {
+      '"' => in_quotes = !in_quotes,
+      ':' if !in_quotes => {
+        let head = &line[..i];
+        let name = match head.find(';') {
+          Some(p) => &head[..p],
+          None => head,
+        };
+        return Some((name.to_uppercase(), &line[i+1..]));
+      },
+      _ => {},
+    }
+  }
+
+  None
+}
+
+fn unescape(value :&str) -> String {
+  let mut text = String::new();
+  let mut chars = value.chars();
+
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      match chars.next() {
+        Some('n') | Some('N') => text.push('\n'),
+        Some(other) => text.push(other),
+        None => {},
+      }
+    } else {
+      text.push(c);
+    }
+  }
+
+  text
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{parse_calendar, occurrences, Occurrence};
+  use chrono::{UTC, TimeZone};
+
+  fn calendar() -> &'static str {
+    "BEGIN:VCALENDAR\r\n\
+VERSION:2.0\r\n\
+BEGIN:VEVENT\r\n\
+UID:hackabend@coredump.ch\r\n\
+SUMMARY:Hackabend\r\n\
+LOCATION:Coredump\\, Rapperswil\r\n\
+DTSTART;TZID=Europe/Zurich:20161017T200000\r\n\
+RRULE:FREQ=WEEKLY;BYDAY=MO\r\n\
+EXDATE;TZID=Europe/Zurich:20161031T200000\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:hackabend@coredump.ch\r\n\
+RECURRENCE-ID;TZID=Europe/Zurich:20161107T200000\r\n\
+SUMMARY:Hackabend with Pizza\r\n\
+DTSTART;TZID=Europe/Zurich:20161107T190000\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:gv@coredump.ch\r\n\
+SUMMARY:General\r\n  Assembly\r\n\
+DTSTART:20161025T170000Z\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:cleanup@coredump.ch\r\n\
+SUMMARY:Cleanup\r\n\
+DTSTART;VALUE=DATE:20161001\r\n\
+RRULE:FREQ=MONTHLY;BYDAY=1SA;COUNT=3\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n"
+  }
+
+  fn summaries(occurrences :&[Occurrence]) -> Vec<(String, String)> {
+    occurrences.iter().map(|o| (o.start.format("%Y-%m-%d %H:%M").to_string(), o.summary.clone())).collect()
+  }
+
+  #[test]
+  fn expand() {
+    let events = parse_calendar(calendar());
+    let found = occurrences(&events, &UTC.ymd(2016, 10, 20).and_hms(0, 0, 0), &UTC.ymd(2016, 12, 31).and_hms(0, 0, 0));
+
+    assert_eq!(vec![
+      ("2016-10-24 18:00".into(), "Hackabend".into()),
+      ("2016-10-25 17:00".into(), "General Assembly".into()),
+      // 2016-10-31 is excluded, summer time is over: 20:00 is 19:00 UTC now
+      ("2016-11-04 23:00".into(), "Cleanup".into()),
+      ("2016-11-07 18:00".into(), "Hackabend with Pizza".into()),
+      ("2016-11-14 19:00".into(), "Hackabend".into()),
+      ("2016-11-21 19:00".into(), "Hackabend".into()),
+      ("2016-11-28 19:00".into(), "Hackabend".into()),
+      ("2016-12-02 23:00".into(), "Cleanup".into()),
+      ("2016-12-05 19:00".into(), "Hackabend".into()),
+      ("2016-12-12 19:00".into(), "Hackabend".into()),
+      ("2016-12-19 19:00".into(), "Hackabend".into()),
+      ("2016-12-26 19:00".into(), "Hackabend".into()),
+    ], summaries(&found));
+  }
+
+  #[test]
+  fn describe() {
+    let events = parse_calendar(calendar());
+    let found = occurrences(&events, &UTC.ymd(2016, 10, 20).and_hms(0, 0, 0), &UTC.ymd(2016, 10, 26).and_hms(0, 0, 0));
+
+    assert_eq!(vec!["Mon 24.10. 20:00 Hackabend (Coredump, Rapperswil)".to_string(), "Tue 25.10. 19:00 General Assembly".into()],
+      found.iter().map(|o| o.describe()).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn running_all_day_event() {
+    let events = parse_calendar(calendar());
+    let found = occurrences(&events, &UTC.ymd(2016, 11, 5).and_hms(12, 0, 0), &UTC.ymd(2016, 11, 6).and_hms(0, 0, 0));
+
+    assert_eq!(vec![("2016-11-04 23:00".into(), "Cleanup".into())], summaries(&found));
+    assert_eq!("Sat 05.11. Cleanup", found[0].describe());
+  }
+
+  #[test]
+  fn long_running_series() {
+    let events = parse_calendar("BEGIN:VCALENDAR\r\n\
+BEGIN:VEVENT\r\n\
+UID:coffee@coredump.ch\r\n\
+SUMMARY:Coffee\r\n\
+DTSTART;TZID=Europe/Zurich:20100104T090000\r\n\
+RRULE:FREQ=DAILY\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:course@coredump.ch\r\n\
+SUMMARY:Course\r\n\
+DTSTART;TZID=Europe/Zurich:20100105T190000\r\n\
+RRULE:FREQ=WEEKLY;COUNT=10\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n");
+    let found = occurrences(&events, &UTC.ymd(2016, 10, 20).and_hms(0, 0, 0), &UTC.ymd(2016, 10, 22).and_hms(0, 0, 0));
+
+    assert_eq!(vec![
+      ("2016-10-20 07:00".into(), "Coffee".into()),
+      ("2016-10-21 07:00".into(), "Coffee".into()),
+    ], summaries(&found));
+  }
+}
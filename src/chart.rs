@@ -0,0 +1,155 @@
+//! Render recorded sensor values as a line chart with plotters
+
+use image::{RgbImage, ImageBuffer};
+use chrono::{UTC, TimeZone};
+use history::Sample;
+use local_time;
+use plotters::prelude::*;
+use plotters::style::{FontStyle, register_font};
+use std::io;
+use std::path::Path;
+use std::sync::{Once, ONCE_INIT};
+
+const WIDTH :u32 = 800;
+const HEIGHT :u32 = 400;
+const MARGIN :u32 = 20;
+/// Room for the values left of the plot and the times below it
+const Y_LABEL_AREA :u32 = 60;
+const X_LABEL_AREA :u32 = 30;
+const FONT_SIZE :u32 = 14;
+/// Number of times below the plot
+const X_LABELS :usize = 5;
+
+const OPEN :RGBColor = RGBColor(210, 240, 210);
+const LINE :RGBColor = RGBColor(200, 30, 30);
+
+/// The labels are drawn with this font, so the chart looks the same on every system
+static FONT :&'static [u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
+static REGISTER_FONT :Once = ONCE_INIT;
+
+/// Render the samples between `from` and `until` (seconds since the unix epoch) to a PNG.
+/// Periods in which the space was open are shaded.
+pub fn render(samples :&[Sample], from :i64, until :i64, path :&Path) -> Result<(), io::Error> {
+  try!(draw(samples, from, until)).save(path)
+}
+
+pub fn draw(samples :&[Sample], from :i64, until :i64) -> Result<RgbImage, io::Error> {
+  REGISTER_FONT.call_once(|| if register_font("sans-serif", FontStyle::Normal, FONT).is_err() {
+    warn!("chart: the font is invalid, the labels are left out");
+  });
+
+  let mut pixels = vec![0; (WIDTH * HEIGHT * 3) as usize];
+  try!(plot(&mut pixels, samples, from, until).map_err(|e| io::Error::new(io::ErrorKind::Other, e)));
+
+  ImageBuffer::from_raw(WIDTH, HEIGHT, pixels)
+    .ok_or(io::Error::new(io::ErrorKind::Other, "the chart does not fit into the picture"))
+}
+
+fn plot(pixels :&mut [u8], samples :&[Sample], from :i64, until :i64) -> Result<(), String> {
+  let until = if until > from { until } else { from + 1 };
+  let (min, max) = value_range(samples);
+
+  let root = BitMapBackend::with_buffer(pixels, (WIDTH, HEIGHT)).into_drawing_area();
+  try!(root.fill(&WHITE).map_err(|e| e.to_string()));
+
+  let mut chart = try!(ChartBuilder::on(&root)
+    .margin(MARGIN)
+    .x_label_area_size(X_LABEL_AREA)
+    .y_label_area_size(Y_LABEL_AREA)
+    .build_cartesian_2d(from..until, min..max)
+    .map_err(|e| e.to_string()));
+
+  // Shade from every sample taken while open to the next sample
+  let open = samples.windows(2).filter(|pair| pair[0].open == Some(true))
+    .map(|pair| Rectangle::new([(pair[0].timestamp, max), (pair[1].timestamp, min)], OPEN.filled()));
+  try!(chart.draw_series(open).map_err(|e| e.to_string()));
+
+  try!(chart.configure_mesh()
+    .disable_mesh()
+    .x_labels(X_LABELS)
+    .x_label_formatter(&|timestamp| format_time(*timestamp))
+    .y_label_formatter(&|value| format_value(*value))
+    .label_style(("sans-serif", FONT_SIZE))
+    .draw()
+    .map_err(|e| e.to_string()));
+
+  let values = samples.iter().map(|sample| (sample.timestamp, sample.value));
+  try!(chart.draw_series(LineSeries::new(values, LINE.stroke_width(2))).map_err(|e| e.to_string()));
+  if samples.len() == 1 {
+    try!(chart.draw_series(samples.iter().map(|sample| Circle::new((sample.timestamp, sample.value), 2, LINE.filled())))
+      .map_err(|e| e.to_string()));
+  }
+
+  root.present().map_err(|e| e.to_string())
+}
+
+/// Lowest and highest value with some padding, never an empty range
+fn value_range(samples :&[Sample]) -> (f64, f64) {
+  let mut min = ::std::f64::INFINITY;
+  let mut max = ::std::f64::NEG_INFINITY;
+  for sample in samples {
+    min = min.min(sample.value);
+    max = max.max(sample.value);
+  }
+
+  if samples.is_empty() {
+    (0.0, 1.0)
+  } else if min == max {
+    (min - 1.0, max + 1.0)
+  } else {
+    let padding = (max - min) * 0.05;
+    (min - padding, max + padding)
+  }
+}
+
+fn format_value(value :f64) -> String {
+  format!("{:.1}", value)
+}
+
+fn format_time(timestamp :i64) -> String {
+  local_time::to_local(&UTC.timestamp(timestamp, 0)).format("%d.%m. %H:%M").to_string()
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{draw, WIDTH, HEIGHT, MARGIN, Y_LABEL_AREA, X_LABEL_AREA, OPEN, LINE};
+  use history::Sample;
+  use image::Rgb;
+
+  fn sample(timestamp :i64, value :f64, open :bool) -> Sample {
+    Sample{ timestamp: timestamp, sensor: "people_now_present".into(), index: 0, value: value, open: Some(open) }
+  }
+
+  #[test]
+  fn dimensions() {
+    let img = draw(&[], 0, 3600).unwrap();
+    assert_eq!((WIDTH, HEIGHT), img.dimensions());
+  }
+
+  #[test]
+  fn shading_and_line() {
+    let samples = vec![sample(0, 0.0, false), sample(1800, 4.0, true), sample(3600, 6.0, true)];
+    let img = draw(&samples, 0, 3600).unwrap();
+    let (left, right, top) = (MARGIN + Y_LABEL_AREA, WIDTH - MARGIN, MARGIN);
+    let (open, line) = (Rgb([OPEN.0, OPEN.1, OPEN.2]), Rgb([LINE.0, LINE.1, LINE.2]));
+
+    // First half closed, second half open
+    assert_eq!(&Rgb([255, 255, 255]), img.get_pixel(left + 100, top + 5));
+    assert_eq!(&open, img.get_pixel(right - 100, top + 5));
+
+    // The line starts at the left edge of the plot
+    assert!((0..HEIGHT).any(|y| (left..left + 3).any(|x| img.get_pixel(x, y) == &line)));
+  }
+
+  #[test]
+  fn labels() {
+    let img = draw(&[sample(0, 20.5, false), sample(3600, 23.0, false)], 0, 3600).unwrap();
+    let dark = |x :u32, y :u32| img.get_pixel(x, y).data.iter().all(|c| *c < 128);
+
+    // Values left of the plot, times below it
+    assert!((0..MARGIN + Y_LABEL_AREA - 5).any(|x| (MARGIN..HEIGHT - MARGIN).any(|y| dark(x, y))));
+    assert!((MARGIN + Y_LABEL_AREA..WIDTH).any(|x| (HEIGHT - MARGIN - X_LABEL_AREA + 5..HEIGHT).any(|y| dark(x, y))));
+  }
+}
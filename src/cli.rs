@@ -0,0 +1,244 @@
+//! One-shot subcommands for scripts, e.g. `coredump_bot status --json`
+//!
+//! The subcommands print the replies the bot would send to a private chat,
+//! as text or with `--json` as an array of objects. If the bot answers with an error message instead,
+//! it is returned, so the caller can print it to stderr and exit with an error code.
+
+use commands::{Context, Handler, Reply, local_chat_id};
+use rustc_serialize::json::{Json, Object};
+use user_input_compiler::Input;
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+pub const USAGE :&'static str = "Usage: coredump_bot [--config FILE] [--repl [DIR] | status | webcam [N] [-o FILE] | grammar | location] [--json]";
+
+#[derive(Debug, PartialEq)]
+pub enum Subcommand {
+  Status,
+  /// `output` is where the picture is copied to, otherwise it stays in the temp directory
+  WebCam{ nth :Option<usize>, output :Option<PathBuf> },
+  Grammar,
+  Location,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Invocation {
+  pub subcommand :Subcommand,
+  pub json :bool,
+}
+
+/// Returns None if there are no arguments, the bot is started then
+pub fn parse(args :&[String]) -> Result<Option<Invocation>, String> {
+  let json = args.iter().any(|a| a == "--json");
+  let mut args = args.iter().filter(|a| *a != "--json").map(|a| &**a);
+
+  let subcommand = match args.next() {
+    None if json => return Err("--json needs a subcommand".into()),
+    None => return Ok(None),
+    Some("status") => Subcommand::Status,
+    Some("grammar") => Subcommand::Grammar,
+    Some("location") => Subcommand::Location,
+    Some("webcam") => {
+      let (mut nth, mut output) = (None, None);
+      while let Some(arg) = args.next() {
+        match arg {
+          "-o" => output = Some(PathBuf::from(try!(args.next().ok_or("-o needs a file name".to_string())))),
+          n => nth = Some(try!(n.parse::<usize>().map_err(|_| format!("Invalid webcam number {:?}", n)))),
+        }
+      }
+      if output.is_some() && nth.is_none() {
+        return Err("-o needs the number of the webcam".into());
+      }
+      Subcommand::WebCam{ nth: nth, output: output }
+    },
+    Some(other) => return Err(format!("Unknown subcommand {:?}", other)),
+  };
+
+  if let Some(extra) = args.next() {
+    return Err(format!("Unexpected argument {:?}", extra));
+  }
+  Ok(Some(Invocation{ subcommand: subcommand, json: json }))
+}
+
+/// Answer the subcommand like a private chat and print the replies, nothing is printed if it fails
+pub fn run<W: Write>(handler :&Handler, invocation :Invocation, output :&mut W) -> Result<(), String> {
+  let context = Context{ chat_id: local_chat_id("cli", ""), private: true, pictures: true, user_id: None };
+  let (input, target) = match invocation.subcommand {
+    Subcommand::Status => (Input::Status, None),
+    Subcommand::WebCam{ nth, output } => (Input::WebCam{ nth: nth }, output),
+    Subcommand::Grammar => (Input::Grammar, None),
+    Subcommand::Location => (Input::Location, None),
+  };
+
+  let expected = expected_reply(&input);
+  let mut replies = handler.handle(&context, input);
+  // Errors are answered with a text, e.g. instead of the picture of a webcam
+  if let Some(position) = replies.iter().position(|reply| !expected(reply)) {
+    return Err(to_text(replies.swap_remove(position)));
+  }
+
+  if let Some(target) = target {
+    for reply in replies.iter_mut() {
+      if let Reply::Photo{ ref mut path, .. } = *reply {
+        try!(fs::copy(&*path, &target).map_err(|e| format!("Unable to write {}: {}", target.display(), e)));
+        *path = target.clone();
+      }
+    }
+  }
+
+  let printed = if invocation.json {
+    writeln!(output, "{}", Json::Array(replies.into_iter().map(to_json).collect()))
+  } else {
+    replies.into_iter().map(|reply| writeln!(output, "{}", to_text(reply))).collect()
+  };
+  printed.map_err(|e| e.to_string())
+}
+
+/// The kind of reply which answers the input, the status is a text even if the space is not reachable
+fn expected_reply(input :&Input) -> fn(&Reply) -> bool {
+  fn text(reply :&Reply) -> bool { match *reply { Reply::Text(_) => true, _ => false } }
+  fn markdown(reply :&Reply) -> bool { match *reply { Reply::Markdown(_) => true, _ => false } }
+  fn photo(reply :&Reply) -> bool { match *reply { Reply::Photo{ .. } => true, _ => false } }
+  fn venue(reply :&Reply) -> bool { match *reply { Reply::Venue{ .. } => true, _ => false } }
+
+  match *input {
+    Input::WebCam{ .. } => photo,
+    Input::Grammar => markdown,
+    Input::Location => venue,
+    _ => text,
+  }
+}
+
+fn to_text(reply :Reply) -> String {
+  match reply {
+    Reply::Text(text) | Reply::Markdown(text) => text,
+    Reply::Photo{ path, caption } => format!("{}\n{}", caption, path.display()),
+    Reply::Document(path) => path.display().to_string(),
+    Reply::Venue{ text, .. } => text,
+  }
+}
+
+fn to_json(reply :Reply) -> Json {
+  let mut object = Object::new();
+  let mut insert = |key :&str, value :Json| { object.insert(key.into(), value); };
+  match reply {
+    Reply::Text(text) | Reply::Markdown(text) => {
+      insert("type", Json::String("text".into()));
+      insert("text", Json::String(text));
+    },
+    Reply::Photo{ path, caption } => {
+      insert("type", Json::String("photo".into()));
+      insert("path", Json::String(path.display().to_string()));
+      insert("caption", Json::String(caption));
+    },
+    Reply::Document(path) => {
+      insert("type", Json::String("document".into()));
+      insert("path", Json::String(path.display().to_string()));
+    },
+    Reply::Venue{ latitude, longitude, title, address, text } => {
+      insert("type", Json::String("location".into()));
+      insert("latitude", Json::F64(latitude));
+      insert("longitude", Json::F64(longitude));
+      insert("title", Json::String(title));
+      insert("address", Json::String(address));
+      insert("text", Json::String(text));
+    },
+  }
+  Json::Object(object)
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{Invocation, Subcommand, parse, run};
+  use commands::Commands;
+  use commands::fixture::FixtureSpace;
+  use events::Events;
+  use history::History;
+  use news::News;
+  use rustc_serialize::json::Json;
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::prelude::*;
+  use std::path::PathBuf;
+  use std::sync::{Arc, Mutex};
+
+  fn args(line :&str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+  }
+
+  fn commands() -> Commands<FixtureSpace> {
+    Commands::new(Arc::new(FixtureSpace::new()),
+      Arc::new(Mutex::new(News::default())), Arc::new(Mutex::new(Events::default())),
+      History::at(env::temp_dir().join("coredump_bot_cli_test.csv")))
+  }
+
+  fn output(invocation :Invocation) -> String {
+    let mut output = vec![];
+    run(&commands(), invocation, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+  }
+
+  #[test]
+  fn parse_arguments() {
+    assert_eq!(Ok(None), parse(&args("")));
+    assert_eq!(Ok(Some(Invocation{ subcommand: Subcommand::Status, json: true })), parse(&args("status --json")));
+    assert_eq!(Ok(Some(Invocation{ subcommand: Subcommand::WebCam{ nth: Some(0), output: Some(PathBuf::from("out.jpg")) }, json: false })),
+      parse(&args("webcam 0 -o out.jpg")));
+    assert_eq!(Ok(Some(Invocation{ subcommand: Subcommand::WebCam{ nth: None, output: None }, json: true })),
+      parse(&args("--json webcam")));
+    assert!(parse(&args("webcam -o out.jpg")).is_err());
+    assert!(parse(&args("webcam zero")).is_err());
+    assert!(parse(&args("status now")).is_err());
+    assert!(parse(&args("open")).is_err());
+    assert!(parse(&args("--json")).is_err());
+  }
+
+  #[test]
+  fn text() {
+    assert_eq!("6 people here right now\n\nThere are 6 people at Hackerspace.\n\nSpaceAPI response contains no temperature sensors.\n",
+      output(Invocation{ subcommand: Subcommand::Status, json: false }));
+    assert_eq!("coredump\nSpinnereistrasse 2, 8640 Rapperswil, Switzerland\n\
+      https://www.openstreetmap.org/?mlat=47.22936&mlon=8.82949#map=18/47.22936/8.82949\n",
+      output(Invocation{ subcommand: Subcommand::Location, json: false }));
+  }
+
+  #[test]
+  fn json() {
+    let location = output(Invocation{ subcommand: Subcommand::Location, json: true });
+    assert_eq!(Json::from_str(r#"[{"type":"location","latitude":47.22936,"longitude":8.82949,"title":"coredump",
+      "address":"Spinnereistrasse 2, 8640 Rapperswil, Switzerland",
+      "text":"coredump\nSpinnereistrasse 2, 8640 Rapperswil, Switzerland\nhttps://www.openstreetmap.org/?mlat=47.22936&mlon=8.82949#map=18/47.22936/8.82949"}]"#).unwrap(),
+      Json::from_str(&location).unwrap());
+  }
+
+  #[test]
+  fn webcam_output() {
+    let target = env::temp_dir().join("coredump_bot_cli_test_out.jpg");
+    let printed = output(Invocation{ subcommand: Subcommand::WebCam{ nth: Some(0), output: Some(target.clone()) }, json: false });
+    assert_eq!(format!("ultimaker.jpg\n{}\n", target.display()), printed);
+
+    let mut picture = String::new();
+    File::open(&target).unwrap().read_to_string(&mut picture).unwrap();
+    assert_eq!("jpeg", picture);
+  }
+
+  #[test]
+  fn failing_webcam() {
+    let target = env::temp_dir().join("coredump_bot_cli_test_failed.jpg");
+    let _ = fs::remove_file(&target);
+
+    let mut output = vec![];
+    let invocation = Invocation{ subcommand: Subcommand::WebCam{ nth: Some(1), output: Some(target.clone()) }, json: true };
+    assert_eq!(Err("The server of the space is not reachable right now 😕 Please try again later.".into()),
+      run(&commands(), invocation, &mut output));
+    assert!(output.is_empty());
+    assert!(!target.exists());
+
+    let invocation = Invocation{ subcommand: Subcommand::WebCam{ nth: Some(2), output: None }, json: false };
+    assert_eq!(Err("You requested the webcam #2, but there are just 2".into()), run(&commands(), invocation, &mut output));
+  }
+}
@@ -0,0 +1,722 @@
+//! Answer the commands independent of the chat network
+//!
+//! A `Handler` turns the parsed `Input` into `Reply`s, the frontends take care of receiving
+//! messages and sending the replies. All requests to the space go through the `Space` trait,
+//! so the commands can be tested against a fixed status.
+
+use calendar::{self, Event};
+use chart;
+use chrono::{self, Datelike};
+use config::{self, Arrival, Features};
+use events::Events;
+use feed_reader::FeedEntry;
+use forecast;
+use geo;
+use grammar;
+use history::{self, History};
+use http_client::HttpClient;
+use local_time;
+use news::News;
+use spaceapi::Location;
+use spaceapi_client::{self, SpaceApiClient, Error};
+use storage;
+use subscriptions::{self, Subscribed};
+use user_input_compiler::{Input, ExportFormat};
+
+use std::cmp;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// How far back the history is considered for /forecast
+const FORECAST_HISTORY_DAYS :i64 = 8 * 7;
+/// Larger exports are refused, Telegram accepts documents up to 50 MB
+const MAX_EXPORT_BYTES :usize = 10 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+  Text(String),
+  /// Text with Markdown formatting, frontends without formatting send it as it is
+  Markdown(String),
+  Photo{ path :PathBuf, caption :String },
+  Document(PathBuf),
+  /// A location with name and address, `text` is used where no map can be shown
+  Venue{ latitude :f64, longitude :f64, title :String, address :String, text :String },
+}
+
+/// Where a command comes from
+pub struct Context {
+  /// Identifies the chat for subscriptions
+  pub chat_id :i64,
+  /// Direct conversation with the bot, in groups invalid commands are ignored
+  pub private :bool,
+  /// False if the frontend can not send pictures, webcams are answered with links then
+  pub pictures :bool,
+  /// The Telegram user who sent the command, decides who is an admin
+  pub user_id :Option<i64>,
+}
+
+/// Telegram chat ids are far above this, the other frontends use the ids below it
+const LOCAL_CHAT_IDS :i64 = -(1 << 52);
+
+/// The chat id of a conversation outside of Telegram like an IRC channel or a Matrix room.
+/// It stays the same across restarts, so the state of the chat is kept.
+pub fn local_chat_id(frontend :&str, conversation :&str) -> i64 {
+  // FNV-1a, the hashers of std may change between releases
+  let mut hash :u64 = 0xcbf29ce484222325;
+  for byte in frontend.bytes().chain(Some(0)).chain(conversation.bytes()) {
+    hash ^= byte as u64;
+    hash = hash.wrapping_mul(0x100000001b3);
+  }
+  LOCAL_CHAT_IDS - (hash >> 12) as i64
+}
+
+pub trait Handler {
+  fn handle(&self, context :&Context, input :Input) -> Vec<Reply>;
+
+  /// Somebody shared a location
+  fn handle_location(&self, context :&Context, latitude :f64, longitude :f64) -> Vec<Reply>;
+
+  /// A message which is neither a command nor a location
+  fn handle_unknown(&self, context :&Context) -> Vec<Reply> {
+    if context.private {
+      vec![Reply::Text("Unknown command. Try /help".into())]
+    } else {
+      vec![]
+    }
+  }
+}
+
+/// What the commands need to know about the space
+pub trait Space {
+  /// The state, the people present and the sensor values
+  fn status(&self) -> Result<String, Error>;
+  fn webcams(&self) -> Vec<String>;
+  /// Download the picture of a webcam and return its path
+  fn webcam_picture(&self, url :&String) -> Result<PathBuf, Error>;
+  fn location(&self) -> Location;
+  fn space_name(&self) -> String;
+  fn address(&self) -> String;
+  fn location_text(&self) -> String;
+  fn news(&self) -> Result<Vec<FeedEntry>, Error>;
+  fn calendar(&self) -> Result<Vec<Event>, Error>;
+  fn projects(&self) -> Result<Vec<(String, Option<String>)>, Error>;
+}
+
+/// The requests are made without holding the lock, it is only taken to read or store the status.
+/// So a slow server does not block the other commands.
+impl Space for Mutex<SpaceApiClient> {
+  fn status(&self) -> Result<String, Error> {
+    try!(refresh(self));
+    let reference = spaceapi_client::trend_reference(&spaceapi_client::trend_window());
+    self.lock().unwrap().aggregated_status(&reference)
+  }
+
+  fn webcams(&self) -> Vec<String> {
+    self.lock().unwrap().get_webcams()
+  }
+
+  fn webcam_picture(&self, url :&String) -> Result<PathBuf, Error> {
+    let http = self.lock().unwrap().http_client();
+    spaceapi_client::get_tmp_path_for_webcam(&http, url).map(PathBuf::from)
+  }
+
+  fn location(&self) -> Location {
+    self.lock().unwrap().get_location()
+  }
+
+  fn space_name(&self) -> String {
+    self.lock().unwrap().get_space_name()
+  }
+
+  fn address(&self) -> String {
+    self.lock().unwrap().get_address()
+  }
+
+  fn location_text(&self) -> String {
+    self.lock().unwrap().get_location_text()
+  }
+
+  fn news(&self) -> Result<Vec<FeedEntry>, Error> {
+    let (http, url) = try!(feed(self, "blog"));
+    spaceapi_client::fetch_news(&http, &url)
+  }
+
+  fn calendar(&self) -> Result<Vec<Event>, Error> {
+    let (http, url) = try!(feed(self, "calendar"));
+    spaceapi_client::fetch_calendar(&http, &url)
+  }
+
+  /// Titles are cached, pages without a title are retried later
+  fn projects(&self) -> Result<Vec<(String, Option<String>)>, Error> {
+    try!(refresh(self));
+    let now = chrono::UTC::now();
+    let (http, urls) = {
+      let client = self.lock().unwrap();
+      (client.http_client(), client.get_projects())
+    };
+
+    Ok(urls.into_iter().map(|url| {
+      let cached = self.lock().unwrap().cached_project_title(&url, &now);
+      let title = match cached {
+        Some(title) => title,
+        None => {
+          let title = spaceapi_client::fetch_project_title(&http, &url);
+          self.lock().unwrap().cache_project_title(url.clone(), title.clone(), &now);
+          title
+        },
+      };
+      (url, title)
+    }).collect())
+  }
+}
+
+/// Fetch the status without holding the lock
+fn refresh(client :&Mutex<SpaceApiClient>) -> Result<(), Error> {
+  let http = client.lock().unwrap().http_client();
+  let fetched = spaceapi_client::fetch_status(&http);
+  client.lock().unwrap().store_status(fetched)
+}
+
+/// The client for requests and the URL of the feed advertised in the refreshed status
+fn feed(client :&Mutex<SpaceApiClient>, kind :&str) -> Result<(Arc<HttpClient>, String), Error> {
+  try!(refresh(client));
+  let client = client.lock().unwrap();
+  let url = try!(client.get_feed_url(kind).ok_or(Error::MissingFeed(kind.into())));
+  Ok((client.http_client(), url))
+}
+
+pub struct Commands<S> {
+  space :Arc<S>,
+  news :Arc<Mutex<News>>,
+  events :Arc<Mutex<Events>>,
+  history :History,
+}
+
+impl<S: Space> Commands<S> {
+  pub fn new(space :Arc<S>, news :Arc<Mutex<News>>, events :Arc<Mutex<Events>>, history :History) -> Commands<S> {
+    Commands{
+      space: space,
+      news: news,
+      events: events,
+      history: history,
+    }
+  }
+
+  fn webcam(&self, context :&Context, nth :Option<usize>) -> Vec<Reply> {
+    let cams = self.space.webcams();
+    let cams :Vec<&String> = match nth {
+      Some(nth) if nth >= cams.len() =>
+        return vec![Reply::Text(format!("You requested the webcam #{}, but there are just {}", nth, cams.len()))],
+      Some(nth) => vec![&cams[nth]],
+      None => cams.iter().collect(),
+    };
+
+    if !context.pictures {
+      return cams.into_iter().map(|url| Reply::Text(url.clone())).collect();
+    }
+
+    cams.into_iter().map(|url| match self.space.webcam_picture(url) {
+      Ok(path) => {
+        let caption = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Reply::Photo{ path: path, caption: caption }
+      },
+      Err(e) => {
+        warn!("Webcam({:?}) Error: {:?}", nth, e);
+        Reply::Text(error_reply(&e))
+      },
+    }).collect()
+  }
+
+  fn chart(&self, context :&Context, sensor :&str, index :u64, duration :&::std::time::Duration) -> Reply {
+    let until = chrono::UTC::now().timestamp();
+    let from = until - duration.as_secs() as i64;
+    let description = format!("{} #{} within the last {}", sensor, index, history::describe_duration(duration));
+
+    match self.history.read_since(sensor, index, from) {
+      Ok(ref samples) if samples.is_empty() => Reply::Text(format!("No values of {} recorded", description)),
+      Ok(samples) => {
+        // Every chat has its own directory, the picture is replaced as a whole while another one may be sent
+        let path = storage::tmp_dir(&format!("chart/{}", context.chat_id))
+          .map(|dir| dir.join(format!("{}_{}.png", sensor, index)))
+          .and_then(|path| storage::replace_file(&path, |tmp_path| chart::render(&samples, from, until, tmp_path)).map(|_| path));
+        match path {
+          Ok(path) => Reply::Photo{ path: path, caption: description },
+          Err(e) => Reply::Text(format!("An error occurred 😕\n{}", e)),
+        }
+      },
+      Err(e) => Reply::Text(format!("An error occurred 😕\n{}", e)),
+    }
+  }
+
+  fn export(&self, context :&Context, sensor :&str, index :u64, duration :&::std::time::Duration, format :ExportFormat) -> Reply {
+    let since = chrono::UTC::now().timestamp() - duration.as_secs() as i64;
+    let name = format!("{}_{}_{}.{}", sensor, index, history::describe_duration(duration), format.extension());
+
+    let samples = match self.history.read_since(sensor, index, since) {
+      Ok(ref samples) if samples.is_empty() =>
+        return Reply::Text(format!("No values of {} #{} recorded within the last {}",
+          sensor, index, history::describe_duration(duration))),
+      Ok(samples) => samples,
+      Err(e) => return Reply::Text(format!("An error occurred 😕\n{}", e)),
+    };
+
+    let body = match format {
+      ExportFormat::Csv => history::to_csv(&samples),
+      ExportFormat::Json => history::to_json(&samples),
+    };
+    if body.len() > MAX_EXPORT_BYTES {
+      return Reply::Text(format!("The export would be {} KiB, the limit is {} KiB. Please choose a shorter duration.",
+        body.len() / 1024, MAX_EXPORT_BYTES / 1024));
+    }
+
+    // The name is shown to the user, so every chat has its own directory instead
+    let path = storage::tmp_dir(&format!("export/{}", context.chat_id))
+      .map(|dir| dir.join(&name))
+      .and_then(|path| storage::replace_file(&path, |tmp_path| File::create(tmp_path)
+        .and_then(|mut f| f.write_all(body.as_bytes())))
+        .map(|_| path));
+    match path {
+      Ok(path) => Reply::Document(path),
+      Err(e) => Reply::Text(format!("An error occurred 😕\n{}", e)),
+    }
+  }
+
+  /// The instructions within their radius of the space, otherwise the distance and direction to it
+  fn directions(&self, arrival :&Arrival, latitude :f64, longitude :f64) -> String {
+    let location = self.space.location();
+    let meters = geo::distance(latitude, longitude, location.lat, location.lon);
+
+    if meters <= arrival.radius_meters {
+      match arrival.instructions {
+        Some(ref instructions) => format!("You are almost there!\n{}", instructions),
+        None => "You are almost there!".into(),
+      }
+    } else {
+      let bearing = geo::bearing(latitude, longitude, location.lat, location.lon);
+      format!("{} is {} to the {} ({:.0}°)", self.space.space_name(),
+        geo::format_distance(meters), geo::compass_point(bearing), bearing)
+    }
+  }
+}
+
+impl<S: Space> Handler for Commands<S> {
+  fn handle(&self, context :&Context, input :Input) -> Vec<Reply> {
+    let config = config::get();
+    if let Some(note) = disabled(&config.features, &input) {
+      return vec![Reply::Text(note.into())];
+    }
+    if let Some(sensor) = unrecorded_sensor(&input) {
+      return vec![Reply::Text(format!("{} is not recorded", sensor))];
+    }
+
+    let text = match input {
+      Input::WebCam{ nth } => return self.webcam(context, nth),
+      Input::Help => config.help_message.clone(),
+      Input::Status => match self.space.status() {
+        Ok(people_now_present) => people_now_present,
+        Err(e) => error_reply(&e),
+      },
+      Input::Start => config.welcome_message.clone(),
+      Input::Version => format!("Version: {}", env!("CARGO_PKG_VERSION")),
+      Input::Grammar => return vec![Reply::Markdown(grammar::get_grammar_string())],
+      Input::Location => {
+        let location = self.space.location();
+        return vec![Reply::Venue{
+          latitude: location.lat,
+          longitude: location.lon,
+          title: self.space.space_name(),
+          address: self.space.address(),
+          text: self.space.location_text(),
+        }];
+      },
+      Input::LocationText => self.space.location_text(),
+      Input::News{ count } => {
+        let count = cmp::min(count.unwrap_or(3), 10);
+        match self.space.news() {
+          Ok(ref entries) if entries.is_empty() => "There are no blog articles yet".into(),
+          Ok(entries) => entries.iter().take(count).map(format_news_entry).collect::<Vec<_>>().join("\n\n"),
+          Err(e) => error_reply(&e),
+        }
+      },
+      Input::NewsSubscription{ enabled } => subscription(&*self.news, context, enabled, "new blog articles"),
+      Input::Events{ days } => {
+        let days = cmp::min(days.unwrap_or(7), 60);
+        match self.space.calendar() {
+          Ok(calendar) => {
+            let now = chrono::UTC::now();
+            let upcoming = calendar::occurrences(&calendar, &now, &(now + chrono::Duration::days(days as i64)));
+            if upcoming.is_empty() {
+              format!("No events within the next {} days", days)
+            } else {
+              upcoming.iter().map(|o| o.describe()).collect::<Vec<_>>().join("\n")
+            }
+          },
+          Err(e) => error_reply(&e),
+        }
+      },
+      Input::EventsSubscription{ enabled } => subscription(&*self.events, context, enabled, "reminders of events"),
+      Input::Projects => match self.space.projects() {
+        Ok(ref projects) if projects.is_empty() => "The SpaceAPI response contains no projects".into(),
+        Ok(projects) => projects.into_iter().map(|(url, title)| match title {
+          Some(title) => format!("{}\n{}", title, url),
+          None => url,
+        }).collect::<Vec<_>>().join("\n\n"),
+        Err(e) => error_reply(&e),
+      },
+      Input::History{ sensor, duration } => {
+        let since = chrono::UTC::now().timestamp() - duration.as_secs() as i64;
+        match self.history.read_since(sensor.sensor(), sensor.index(), since) {
+          Ok(samples) => match history::statistics(&samples) {
+            Some(stats) => format!("{} #{} within the last {} ({} samples)\nmin {}, max {}, average {:.1}, last {}",
+              sensor.sensor(), sensor.index(), history::describe_duration(&duration), stats.count,
+              stats.min, stats.max, stats.average, stats.last),
+            None => format!("No values of {} #{} recorded within the last {}",
+              sensor.sensor(), sensor.index(), history::describe_duration(&duration)),
+          },
+          Err(e) => format!("An error occurred 😕\n{}", e),
+        }
+      },
+      Input::Chart{ sensor, duration } => return vec![self.chart(context, sensor.sensor(), sensor.index(), &duration)],
+      Input::Forecast{ weekday } => {
+        let weekday = weekday.unwrap_or_else(|| local_time::now().weekday());
+        let since = chrono::UTC::now().timestamp() - FORECAST_HISTORY_DAYS * 24 * 60 * 60;
+        match self.history.read_since("people_now_present", 0, since) {
+          Ok(samples) => forecast::Heatmap::from_samples(&samples).describe_day(weekday),
+          Err(e) => format!("An error occurred 😕\n{}", e),
+        }
+      },
+      Input::Export{ sensor, duration, format } =>
+        return vec![self.export(context, sensor.sensor(), sensor.index(), &duration, format)],
+      Input::Reload => match context.user_id {
+        Some(user_id) if config.is_admin(user_id) => match config::reload() {
+          Ok(()) => "The configuration is reloaded".into(),
+          Err(e) => format!("The configuration is invalid, the previous one stays active:\n{}", e),
+        },
+        _ => "Only admins may reload the configuration".into(),
+      },
+      Input::InvalidSyntax( msg ) => {
+        if !context.private {
+          return vec![];
+        }
+        format!("Invalid syntax: {}\ntry /grammar", msg)
+      },
+      _ => return self.handle_unknown(context),
+    };
+
+    vec![Reply::Text(text)]
+  }
+
+  fn handle_location(&self, _context :&Context, latitude :f64, longitude :f64) -> Vec<Reply> {
+    vec![Reply::Text(self.directions(&config::get().arrival, latitude, longitude))]
+  }
+
+}
+
+/// Subscribe or unsubscribe the chat, `what` it receives is named in the answer
+fn subscription<T: Subscribed>(state :&Mutex<T>, context :&Context, enabled :bool, what :&str) -> String {
+  match (enabled, subscriptions::set(state, context.chat_id, enabled)) {
+    (true, true) => format!("{} will be posted here", capitalize(what)),
+    (true, false) => format!("This chat already receives {}", what),
+    (false, true) => format!("{} will no longer be posted here", capitalize(what)),
+    (false, false) => format!("This chat does not receive {}", what),
+  }
+}
+
+fn capitalize(s :&str) -> String {
+  let mut chars = s.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().chain(chars).collect(),
+    None => String::new(),
+  }
+}
+
+/// The commands of disabled features are answered with this note
+fn disabled(features :&Features, input :&Input) -> Option<&'static str> {
+  match *input {
+    Input::News{ .. } | Input::NewsSubscription{ .. } if !features.news => Some("The news are disabled"),
+    Input::Events{ .. } | Input::EventsSubscription{ .. } if !features.events => Some("The events are disabled"),
+    Input::History{ .. } | Input::Chart{ .. } | Input::Forecast{ .. } | Input::Export{ .. } if !features.history =>
+      Some("The sensor history is disabled"),
+    Input::WebCam{ .. } if !features.webcam => Some("The webcams are disabled"),
+    _ => None,
+  }
+}
+
+/// The sensor of /history, /chart or /export if its values are not recorded
+fn unrecorded_sensor(input :&Input) -> Option<&str> {
+  match *input {
+    Input::History{ ref sensor, .. } | Input::Chart{ ref sensor, .. } | Input::Export{ ref sensor, .. }
+      if !history::is_recorded(sensor.sensor()) => Some(sensor.sensor()),
+    _ => None,
+  }
+}
+
+pub fn format_news_entry(entry :&FeedEntry) -> String {
+  format!("{}\n{}", entry.title, entry.link)
+}
+
+/// Reply for the user, the details go to the log
+fn error_reply(e :&Error) -> String {
+  warn!("SpaceAPI client error: {}", e);
+  match *e {
+    Error::Network(_) => "The server of the space is not reachable right now 😕 Please try again later.".into(),
+    Error::HttpStatus(ref status) => format!("The server of the space answered with {} 😕 Please try again later.", status),
+    Error::Decode(_) => "The server of the space sent something I do not understand 😕".into(),
+    Error::EmptyResponse(_) => "The server of the space sent an empty response 😕 Please try again later.".into(),
+    Error::MissingSensor(ref name) => format!("The space publishes no {} sensors", name),
+    Error::MissingFeed(ref kind) => format!("The space publishes no {} feed", kind),
+    Error::Io(ref e) => format!("An error occurred 😕\n{}", e),
+  }
+}
+
+
+
+/// Stand-ins for the tests of the commands and the frontends
+#[cfg(test)]
+pub mod fixture {
+  use super::{Context, Handler, Reply, Space};
+  use calendar::Event;
+  use feed_reader::FeedEntry;
+  use spaceapi::{Location, Status};
+  use spaceapi_client::{self, Error};
+  use storage;
+  use user_input_compiler::Input;
+  use rustc_serialize::json;
+  use std::env;
+  use std::fs::File;
+  use std::io::prelude::*;
+  use std::path::PathBuf;
+  use std::time::Duration;
+
+  /// Answers with the debug output of the input, a webcam picture containing "jpeg" and a fixed venue
+  pub struct FixtureHandler;
+
+  impl Handler for FixtureHandler {
+    fn handle(&self, _context :&Context, input :Input) -> Vec<Reply> {
+      match input {
+        Input::WebCam{ .. } => {
+          // Replaced as a whole, another test may be reading it
+          let path = env::temp_dir().join("coredump_bot_fixture_webcam.jpg");
+          storage::replace_file(&path, |tmp_path| File::create(tmp_path).and_then(|mut f| f.write_all(b"jpeg"))).unwrap();
+          vec![Reply::Photo{ path: path, caption: "ultimaker.jpg".into() }]
+        },
+        Input::Location => vec![Reply::Venue{ latitude: 47.5, longitude: 8.75, title: "coredump".into(),
+          address: "Rapperswil".into(), text: "coredump\nRapperswil".into() }],
+        input => vec![Reply::Text(format!("{:?}", input))],
+      }
+    }
+
+    fn handle_location(&self, _context :&Context, latitude :f64, longitude :f64) -> Vec<Reply> {
+      vec![Reply::Text(format!("shared {} {}", latitude, longitude))]
+    }
+  }
+
+  /// A space which answers from a fixed status and never goes online
+  pub struct FixtureSpace {
+    status :Status,
+  }
+
+  impl FixtureSpace {
+    /// Two webcams, the second one is broken
+    pub fn new() -> FixtureSpace {
+      let status = json::decode("{\"api\":\"0.13\",\"cam\":[\"https://webcam.coredump.ch/cams/ultimaker.jpg\",\"https://webcam.coredump.ch/cams/broken.jpg\"],\"contact\":{},\"issue_report_channels\":[\"email\"],\"location\":{\"address\":\"Spinnereistrasse 2, 8640 Rapperswil, Switzerland\",\"lat\":47.22936,\"lon\":8.82949},\"logo\":\"https://www.coredump.ch/logo.png\",\"sensors\":{\"people_now_present\":[{\"location\":\"Hackerspace\",\"value\":6}]},\"space\":\"coredump\",\"state\":{\"message\":\"6 people here right now\",\"open\":true},\"url\":\"https://www.coredump.ch/\"}").unwrap();
+      FixtureSpace{ status: status }
+    }
+  }
+
+  impl Space for FixtureSpace {
+    fn status(&self) -> Result<String, Error> {
+      spaceapi_client::aggregate_status(self.status.clone(), &[], &Duration::from_secs(60*60))
+    }
+
+    fn webcams(&self) -> Vec<String> {
+      spaceapi_client::webcams(&self.status)
+    }
+
+    fn webcam_picture(&self, url :&String) -> Result<PathBuf, Error> {
+      if url.ends_with("broken.jpg") {
+        return Err(Error::Network("connection refused".into()));
+      }
+      let path = env::temp_dir().join(url.rsplit('/').next().unwrap());
+      try!(storage::replace_file(&path, |tmp_path| File::create(tmp_path).and_then(|mut f| f.write_all(b"jpeg"))));
+      Ok(path)
+    }
+
+    fn location(&self) -> Location {
+      self.status.location.clone()
+    }
+
+    fn space_name(&self) -> String {
+      self.status.space.clone()
+    }
+
+    fn address(&self) -> String {
+      spaceapi_client::address(&self.status)
+    }
+
+    fn location_text(&self) -> String {
+      spaceapi_client::location_text(&self.status)
+    }
+
+    fn news(&self) -> Result<Vec<FeedEntry>, Error> {
+      Ok(vec![
+        FeedEntry{ id: "2".into(), title: "Repair Café".into(), link: "https://www.coredump.ch/2".into() },
+        FeedEntry{ id: "1".into(), title: "Hello".into(), link: "https://www.coredump.ch/1".into() },
+      ])
+    }
+
+    fn calendar(&self) -> Result<Vec<Event>, Error> {
+      Err(Error::MissingFeed("calendar".into()))
+    }
+
+    fn projects(&self) -> Result<Vec<(String, Option<String>)>, Error> {
+      Ok(vec![
+        ("https://www.coredump.ch/projekte/".into(), Some("Projekte".into())),
+        ("https://github.com/coredump-ch/".into(), None),
+      ])
+    }
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{Commands, Context, Handler, Reply, disabled, local_chat_id, LOCAL_CHAT_IDS};
+  use super::fixture::FixtureSpace;
+  use config::{Arrival, Config};
+  use events::Events;
+  use history::History;
+  use news::News;
+  use user_input_compiler::Input;
+  use std::env;
+  use std::sync::{Arc, Mutex};
+
+  fn commands() -> Commands<FixtureSpace> {
+    Commands::new(Arc::new(FixtureSpace::new()),
+      Arc::new(Mutex::new(News::default())), Arc::new(Mutex::new(Events::default())),
+      History::at(env::temp_dir().join("coredump_bot_commands_test.csv")))
+  }
+
+  fn private() -> Context {
+    Context{ chat_id: 1, private: true, pictures: true, user_id: None }
+  }
+
+  fn group() -> Context {
+    Context{ chat_id: -1, private: false, pictures: true, user_id: None }
+  }
+
+  fn text(s :&str) -> Vec<Reply> {
+    vec![Reply::Text(s.into())]
+  }
+
+  #[test]
+  fn local_chat_ids() {
+    let ids = [local_chat_id("repl", ""), local_chat_id("irc", "#coredump"), local_chat_id("irc", "#coredump-dev"),
+      local_chat_id("matrix", "#coredump")];
+    for (i, id) in ids.iter().enumerate() {
+      assert!(*id < LOCAL_CHAT_IDS);
+      assert!(ids[i + 1..].iter().all(|other| other != id));
+    }
+    assert_eq!(ids[1], local_chat_id("irc", "#coredump"));
+  }
+
+  #[test]
+  fn status() {
+    assert_eq!(text("6 people here right now\n\nThere are 6 people at Hackerspace.\n\nSpaceAPI response contains no temperature sensors."),
+      commands().handle(&private(), Input::Status));
+  }
+
+  #[test]
+  fn webcam() {
+    assert_eq!(vec![
+      Reply::Photo{ path: env::temp_dir().join("ultimaker.jpg"), caption: "ultimaker.jpg".into() },
+      Reply::Text("The server of the space is not reachable right now 😕 Please try again later.".into()),
+    ], commands().handle(&private(), Input::WebCam{ nth: None }));
+  }
+
+  #[test]
+  fn webcam_links() {
+    let context = Context{ chat_id: 0, private: false, pictures: false, user_id: None };
+    assert_eq!(text("https://webcam.coredump.ch/cams/broken.jpg"), commands().handle(&context, Input::WebCam{ nth: Some(1) }));
+  }
+
+  #[test]
+  fn webcam_out_of_range() {
+    assert_eq!(text("You requested the webcam #2, but there are just 2"),
+      commands().handle(&private(), Input::WebCam{ nth: Some(2) }));
+  }
+
+  #[test]
+  fn location() {
+    assert_eq!(vec![Reply::Venue{
+      latitude: 47.22936,
+      longitude: 8.82949,
+      title: "coredump".into(),
+      address: "Spinnereistrasse 2, 8640 Rapperswil, Switzerland".into(),
+      text: "coredump\nSpinnereistrasse 2, 8640 Rapperswil, Switzerland\nhttps://www.openstreetmap.org/?mlat=47.22936&mlon=8.82949#map=18/47.22936/8.82949".into(),
+    }], commands().handle(&private(), Input::Location));
+  }
+
+  #[test]
+  fn shared_location() {
+    let arrival = Arrival{ radius_meters: 150.0, instructions: Some("Ring twice".into()) };
+    assert_eq!("You are almost there!\nRing twice", commands().directions(&arrival, 47.2294, 8.8295));
+    assert_eq!("coredump is 30.2 km to the east (90°)", commands().directions(&arrival, 47.2294, 8.43));
+    assert_eq!("You are almost there!", commands().directions(&Arrival{ radius_meters: 50_000.0, instructions: None }, 47.2294, 8.43));
+  }
+
+  #[test]
+  fn news() {
+    assert_eq!(text("Repair Café\nhttps://www.coredump.ch/2"), commands().handle(&private(), Input::News{ count: Some(1) }));
+  }
+
+  #[test]
+  fn events_without_calendar() {
+    assert_eq!(text("The space publishes no calendar feed"), commands().handle(&private(), Input::Events{ days: None }));
+  }
+
+  #[test]
+  fn projects() {
+    assert_eq!(text("Projekte\nhttps://www.coredump.ch/projekte/\n\nhttps://github.com/coredump-ch/"),
+      commands().handle(&private(), Input::Projects));
+  }
+
+  #[test]
+  fn invalid_syntax() {
+    assert_eq!(text("Invalid syntax: Invalid CommandWord\ntry /grammar"),
+      commands().handle(&private(), Input::InvalidSyntax("Invalid CommandWord".into())));
+    assert_eq!(Vec::<Reply>::new(), commands().handle(&group(), Input::InvalidSyntax("Invalid CommandWord".into())));
+  }
+
+  #[test]
+  fn unknown() {
+    assert_eq!(text("Unknown command. Try /help"), commands().handle(&private(), Input::Cancel));
+    assert_eq!(Vec::<Reply>::new(), commands().handle_unknown(&group()));
+  }
+
+  #[test]
+  fn unrecorded_sensor() {
+    assert_eq!(text("humidity is not recorded"), commands().handle(&private(), Input::from("/history humidity 2h".to_string())));
+    assert_eq!(text("wind is not recorded"), commands().handle(&private(), Input::from("/chart wind 2h".to_string())));
+  }
+
+  #[test]
+  fn reload_only_for_admins() {
+    assert_eq!(text("Only admins may reload the configuration"), commands().handle(&private(), Input::Reload));
+  }
+
+  #[test]
+  fn disabled_features() {
+    let mut features = Config::default().features;
+    assert_eq!(None, disabled(&features, &Input::WebCam{ nth: None }));
+
+    features.webcam = false;
+    features.history = false;
+    assert_eq!(Some("The webcams are disabled"), disabled(&features, &Input::WebCam{ nth: None }));
+    assert_eq!(Some("The sensor history is disabled"), disabled(&features, &Input::Forecast{ weekday: None }));
+    assert_eq!(None, disabled(&features, &Input::News{ count: None }));
+  }
+}
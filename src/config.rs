@@ -0,0 +1,728 @@
+//! Settings from a TOML file, each of them may be overridden with an environment variable
+//!
+//! The file is `coredump_bot.toml` in the working directory, `--config FILE` or `COREDUMP_BOT_CONFIG` choose another one.
+//! Missing settings keep their defaults, unknown ones and invalid values are refused with a message naming them.
+
+use toml::{Parser, Table, Value};
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+pub const DEFAULT_PATH :&'static str = "coredump_bot.toml";
+
+/// Section and key in the file and the environment variable overriding it
+const SETTINGS :&'static [(&'static str, &'static str, &'static str)] = &[
+  ("telegram", "token", "TELEGRAM_BOT_TOKEN"),
+  ("telegram", "username", "COREDUMP_BOT_USERNAME"),
+  ("telegram", "admins", "COREDUMP_BOT_ADMINS"),
+  ("space", "status_url", "COREDUMP_BOT_STATUS_URL"),
+  ("space", "trend_minutes", "COREDUMP_BOT_TREND_MINUTES"),
+  ("backoff", "min_seconds", "COREDUMP_BOT_MIN_BACKOFF_SECONDS"),
+  ("backoff", "max_seconds", "COREDUMP_BOT_MAX_BACKOFF_SECONDS"),
+  ("storage", "data_dir", "COREDUMP_BOT_DATA_DIR"),
+  ("storage", "temp_dir_name", "COREDUMP_BOT_TEMP_DIR_NAME"),
+  ("messages", "welcome", "COREDUMP_BOT_WELCOME_MESSAGE"),
+  ("messages", "help", "COREDUMP_BOT_HELP_MESSAGE"),
+  ("features", "news", "COREDUMP_BOT_FEATURE_NEWS"),
+  ("features", "events", "COREDUMP_BOT_FEATURE_EVENTS"),
+  ("features", "history", "COREDUMP_BOT_FEATURE_HISTORY"),
+  ("features", "webcam", "COREDUMP_BOT_FEATURE_WEBCAM"),
+  ("arrival", "radius_meters", "COREDUMP_BOT_ARRIVAL_RADIUS"),
+  ("arrival", "instructions", "COREDUMP_BOT_ARRIVAL_INSTRUCTIONS"),
+  ("irc", "server", "COREDUMP_BOT_IRC_SERVER"),
+  ("irc", "nick", "COREDUMP_BOT_IRC_NICK"),
+  ("irc", "channels", "COREDUMP_BOT_IRC_CHANNELS"),
+  ("matrix", "homeserver", "COREDUMP_BOT_MATRIX_HOMESERVER"),
+  ("matrix", "access_token", "COREDUMP_BOT_MATRIX_ACCESS_TOKEN"),
+  ("matrix", "rooms", "COREDUMP_BOT_MATRIX_ROOMS"),
+  ("webhook", "url", "COREDUMP_BOT_WEBHOOK_URL"),
+  ("webhook", "secret", "COREDUMP_BOT_WEBHOOK_SECRET"),
+  ("webhook", "listen", "COREDUMP_BOT_WEBHOOK_LISTEN"),
+  ("webhook", "cert", "COREDUMP_BOT_WEBHOOK_CERT"),
+  ("webhook", "key", "COREDUMP_BOT_WEBHOOK_KEY"),
+];
+
+const DEFAULT_IRC_NICK :&'static str = "CoredumpBot";
+/// Telegram only delivers to the ports 443, 80, 88 and 8443
+const DEFAULT_WEBHOOK_LISTEN :&'static str = "0.0.0.0:8443";
+
+const DEFAULT_WELCOME :&'static str = "Welcome to CoredumpBot\nuse /help to see available commands.";
+const DEFAULT_HELP :&'static str = "No such help 😜\n\
+  use /webcam for a snapshot of the 3D printer.\n\
+  use /status for an update on people now present\n\
+  use /location for the address, /location text if your client shows no map\n\
+  use /news for the latest blog articles, /news on to get them automatically\n\
+  use /events for upcoming events, /events on for a reminder before they start\n\
+  use /projects for links to our projects\n\
+  use /history temperature 2h for statistics of a sensor, /chart temperature 2h for a chart\n\
+  use /export temperature 7d csv to download the recorded values\n\
+  use /forecast monday to see how likely the space is open and how crowded it usually is\n\
+  use /grammar to receive the spec";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+  /// The file the settings were read from, it is read again by `reload`
+  pub path :Option<PathBuf>,
+  /// Only the REPL and the subcommands work without a token
+  pub token :Option<String>,
+  /// Name of the bot without the @, commands in groups may be addressed to it like `/status@CoreDumpBot`
+  pub username :String,
+  /// Telegram user ids allowed to use the admin commands
+  pub admins :Vec<i64>,
+  pub status_url :String,
+  /// The values in /status are compared with the recorded ones this long ago
+  pub trend_minutes :u64,
+  /// Bounds of the delay after failing to fetch updates or to connect to IRC and Matrix
+  pub min_backoff_seconds :u64,
+  pub max_backoff_seconds :u64,
+  pub data_dir :PathBuf,
+  /// Directory for temporary files within the temp directory of the system
+  pub temp_dir_name :String,
+  /// Answer to /start
+  pub welcome_message :String,
+  pub help_message :String,
+  pub features :Features,
+  pub arrival :Arrival,
+  /// Answering on IRC, enabled by a server
+  pub irc :Option<Irc>,
+  /// Answering on Matrix, enabled by a homeserver and an access token
+  pub matrix :Option<Matrix>,
+  /// Receiving the updates with a webhook instead of long polling, enabled by a URL
+  pub webhook :Option<Webhook>,
+}
+
+/// Disabled features answer their commands with a short note and stop their background work
+#[derive(Debug, Clone, PartialEq)]
+pub struct Features {
+  /// /news and announcing new blog articles
+  pub news :bool,
+  /// /events and the reminders
+  pub events :bool,
+  /// Recording the sensor values, /history, /chart, /forecast and /export
+  pub history :bool,
+  pub webcam :bool,
+}
+
+/// Users sharing their location get the distance and direction to the space, or the instructions once they are close
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arrival {
+  /// Within this many meters of the space the instructions are sent instead of directions
+  pub radius_meters :f64,
+  pub instructions :Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Irc {
+  /// host:port, e.g. `chat.freenode.net:6667`
+  pub server :String,
+  pub nick :String,
+  pub channels :Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+  /// e.g. `https://matrix.org`, without a trailing slash
+  pub homeserver :String,
+  pub access_token :String,
+  /// Room ids or aliases like `#coredump:matrix.org`, joined on startup
+  pub rooms :Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+  /// Public URL of the bot without the secret, e.g. `https://bot.example.com`
+  pub url :String,
+  pub listen :String,
+  pub secret :String,
+  /// Certificate and private key in PEM format, without them plain HTTP is served, e.g. behind a reverse proxy
+  pub tls :Option<(String, String)>,
+}
+
+impl Webhook {
+  /// The URL to register with setWebhook
+  pub fn endpoint(&self) -> String {
+    format!("{}/{}", self.url.trim_right_matches('/'), self.secret)
+  }
+}
+
+impl Default for Config {
+  fn default() -> Config {
+    Config{
+      path: None,
+      token: None,
+      username: "CoreDumpBot".into(),
+      admins: vec![],
+      status_url: "https://status.crdmp.ch/".into(),
+      trend_minutes: 60,
+      min_backoff_seconds: 1,
+      max_backoff_seconds: 128,
+      data_dir: PathBuf::from("data"),
+      temp_dir_name: "coredump_bot".into(),
+      welcome_message: DEFAULT_WELCOME.into(),
+      help_message: DEFAULT_HELP.into(),
+      features: Features{ news: true, events: true, history: true, webcam: true },
+      arrival: Arrival{ radius_meters: 150.0, instructions: None },
+      irc: None,
+      matrix: None,
+      webhook: None,
+    }
+  }
+}
+
+impl Config {
+  pub fn is_admin(&self, user_id :i64) -> bool {
+    self.admins.contains(&user_id)
+  }
+}
+
+lazy_static! {
+  static ref CURRENT :RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+/// The active configuration
+pub fn get() -> Arc<Config> {
+  CURRENT.read().unwrap().clone()
+}
+
+/// Make `config` the active configuration
+pub fn set(config :Config) {
+  *CURRENT.write().unwrap() = Arc::new(config);
+}
+
+/// Read the file and apply the environment.
+/// Without `path` the default file is used if it exists, otherwise only the environment.
+/// The error lists every problem on its own line.
+pub fn load(path :Option<&Path>) -> Result<Config, String> {
+  let path = match path.map(PathBuf::from).or(env::var("COREDUMP_BOT_CONFIG").ok().map(PathBuf::from)) {
+    Some(path) => Some(path),
+    None if Path::new(DEFAULT_PATH).exists() => Some(PathBuf::from(DEFAULT_PATH)),
+    None => None,
+  };
+  let text = match path {
+    Some(ref path) => try!(read(path)),
+    None => String::new(),
+  };
+
+  let mut config = try!(from_toml(&text, |var| env::var(var).ok()));
+  config.path = path;
+  Ok(config)
+}
+
+/// Read the file and the environment again and make them the active configuration.
+/// If they are invalid or change a setting which is only read on startup, the active configuration is kept.
+pub fn reload() -> Result<(), String> {
+  let current = get();
+  let config = try!(load(current.path.as_ref().map(|p| p.as_path())));
+  try!(check_reload(&current, &config));
+
+  set(config);
+  info!("Configuration reloaded");
+  Ok(())
+}
+
+/// The token, the data directory, IRC, Matrix and the webhook are in use since the start
+fn check_reload(current :&Config, config :&Config) -> Result<(), String> {
+  let mut errors = vec![];
+  if config.token != current.token {
+    errors.push("telegram.token: changing the token needs a restart");
+  }
+  if config.data_dir != current.data_dir {
+    errors.push("storage.data_dir: changing the data directory needs a restart");
+  }
+  if config.irc != current.irc {
+    errors.push("irc: changing IRC needs a restart");
+  }
+  if config.matrix != current.matrix {
+    errors.push("matrix: changing Matrix needs a restart");
+  }
+  if config.webhook != current.webhook {
+    errors.push("webhook: changing the webhook needs a restart");
+  }
+
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors.join("\n"))
+  }
+}
+
+fn read(path :&Path) -> Result<String, String> {
+  let mut text = String::new();
+  try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text)).map_err(|e| format!("{}: {}", path.display(), e)));
+  Ok(text)
+}
+
+/// Parse the file and apply the overrides `env` returns for the variable names
+pub fn from_toml<F>(text :&str, env :F) -> Result<Config, String> where F: Fn(&str) -> Option<String> {
+  let mut parser = Parser::new(text);
+  let table = match parser.parse() {
+    Some(table) => table,
+    None => return Err(parser.errors.iter().map(|e| {
+      let (line, column) = parser.to_linecol(e.lo);
+      format!("line {}, column {}: {}", line + 1, column + 1, e.desc)
+    }).collect::<Vec<_>>().join("\n")),
+  };
+
+  let mut reader = Reader{ table: table, env: &env, errors: vec![] };
+  reader.check_unknown();
+
+  let mut config = Config::default();
+  if let Some(token) = reader.string("telegram", "token") { config.token = Some(token); }
+  if let Some(username) = reader.string("telegram", "username") { config.username = username; }
+  if let Some(admins) = reader.integers("telegram", "admins") { config.admins = admins; }
+  if let Some(url) = reader.string("space", "status_url") { config.status_url = url; }
+  if let Some(minutes) = reader.unsigned("space", "trend_minutes", "minutes") { config.trend_minutes = minutes; }
+  if let Some(seconds) = reader.unsigned("backoff", "min_seconds", "seconds") { config.min_backoff_seconds = seconds; }
+  if let Some(seconds) = reader.unsigned("backoff", "max_seconds", "seconds") { config.max_backoff_seconds = seconds; }
+  if let Some(dir) = reader.string("storage", "data_dir") { config.data_dir = PathBuf::from(dir); }
+  if let Some(name) = reader.string("storage", "temp_dir_name") { config.temp_dir_name = name; }
+  if let Some(message) = reader.string("messages", "welcome") { config.welcome_message = message; }
+  if let Some(message) = reader.string("messages", "help") { config.help_message = message; }
+  if let Some(enabled) = reader.boolean("features", "news") { config.features.news = enabled; }
+  if let Some(enabled) = reader.boolean("features", "events") { config.features.events = enabled; }
+  if let Some(enabled) = reader.boolean("features", "history") { config.features.history = enabled; }
+  if let Some(enabled) = reader.boolean("features", "webcam") { config.features.webcam = enabled; }
+  if let Some(meters) = reader.float("arrival", "radius_meters") { config.arrival.radius_meters = meters; }
+  if let Some(instructions) = reader.string("arrival", "instructions") { config.arrival.instructions = Some(instructions); }
+
+  let (server, nick, channels) = (reader.string("irc", "server"), reader.string("irc", "nick"), reader.strings("irc", "channels"));
+  match server {
+    Some(server) => config.irc = Some( Irc{
+      server: server,
+      nick: nick.unwrap_or(DEFAULT_IRC_NICK.into()),
+      channels: channels.unwrap_or_default(),
+    }),
+    None if nick.is_some() || channels.is_some() => reader.errors.push("irc.server: required for the other IRC settings".into()),
+    None => (),
+  }
+
+  let (homeserver, access_token, rooms) = (reader.string("matrix", "homeserver"), reader.string("matrix", "access_token"),
+    reader.strings("matrix", "rooms"));
+  match (homeserver, access_token) {
+    (Some(homeserver), Some(access_token)) => config.matrix = Some( Matrix{
+      homeserver: homeserver.trim_right_matches('/').into(),
+      access_token: access_token,
+      rooms: rooms.unwrap_or_default(),
+    }),
+    (None, None) if rooms.is_none() => (),
+    _ => reader.errors.push("matrix.homeserver, matrix.access_token: both are required for Matrix".into()),
+  }
+
+  let (url, secret, listen) = (reader.string("webhook", "url"), reader.string("webhook", "secret"), reader.string("webhook", "listen"));
+  let tls = match (reader.string("webhook", "cert"), reader.string("webhook", "key")) {
+    (Some(cert), Some(key)) => Some((cert, key)),
+    (None, None) => None,
+    _ => {
+      reader.errors.push("webhook.cert, webhook.key: have to be set together".into());
+      None
+    },
+  };
+  match (url, secret) {
+    (Some(url), Some(secret)) => config.webhook = Some( Webhook{
+      url: url,
+      listen: listen.unwrap_or(DEFAULT_WEBHOOK_LISTEN.into()),
+      secret: secret,
+      tls: tls,
+    }),
+    (Some(_), None) => reader.errors.push("webhook.secret: required for the webhook".into()),
+    (None, None) if listen.is_none() && tls.is_none() => (),
+    (None, _) => reader.errors.push("webhook.url: required for the other webhook settings".into()),
+  }
+
+  let mut errors = reader.errors;
+  errors.extend(validate(&config));
+  if errors.is_empty() {
+    Ok(config)
+  } else {
+    Err(errors.join("\n"))
+  }
+}
+
+fn validate(config :&Config) -> Vec<String> {
+  let mut errors = vec![];
+
+  if let Some(ref token) = config.token {
+    let mut parts = token.splitn(2, ':');
+    let id = parts.next().unwrap_or("");
+    if id.is_empty() || !id.chars().all(|c| c.is_digit(10)) || parts.next().map_or(true, |s| s.is_empty()) {
+      errors.push("telegram.token: expected a token like 123456:ABC-DEF from @BotFather".into());
+    }
+  }
+  if config.username.is_empty() || !config.username.chars().all(|c| c.is_alphanumeric() || c == '_') {
+    errors.push("telegram.username: expected the name of the bot without the @".into());
+  }
+  if config.admins.iter().any(|id| *id <= 0) {
+    errors.push("telegram.admins: user ids are positive, groups can not be admins".into());
+  }
+  if !config.status_url.starts_with("http://") && !config.status_url.starts_with("https://") {
+    errors.push(format!("space.status_url: expected an http(s) URL, got {:?}", config.status_url));
+  }
+  if config.trend_minutes == 0 {
+    errors.push("space.trend_minutes: has to be at least 1".into());
+  }
+  if config.min_backoff_seconds == 0 {
+    errors.push("backoff.min_seconds: has to be at least 1".into());
+  }
+  if config.min_backoff_seconds > config.max_backoff_seconds {
+    errors.push(format!("backoff.max_seconds: has to be at least min_seconds ({})", config.min_backoff_seconds));
+  }
+  if config.data_dir.as_os_str().is_empty() {
+    errors.push("storage.data_dir: may not be empty".into());
+  }
+  if config.temp_dir_name.is_empty() || config.temp_dir_name == ".." || config.temp_dir_name.contains(|c| c == '/' || c == '\\') {
+    errors.push(format!("storage.temp_dir_name: expected a single directory name, got {:?}", config.temp_dir_name));
+  }
+  if config.welcome_message.trim().is_empty() {
+    errors.push("messages.welcome: may not be empty".into());
+  }
+  if config.help_message.trim().is_empty() {
+    errors.push("messages.help: may not be empty".into());
+  }
+  if !(config.arrival.radius_meters >= 0.0) || config.arrival.radius_meters.is_infinite() {
+    errors.push(format!("arrival.radius_meters: expected a distance in meters, got {}", config.arrival.radius_meters));
+  }
+  if config.arrival.instructions.as_ref().map_or(false, |i| i.trim().is_empty()) {
+    errors.push("arrival.instructions: may not be empty".into());
+  }
+
+  if let Some(ref irc) = config.irc {
+    if !is_address(&irc.server) {
+      errors.push(format!("irc.server: expected host:port, got {:?}", irc.server));
+    }
+    if irc.nick.is_empty() || irc.nick.contains(|c :char| c.is_whitespace() || c == ',' || c == ':' || c == '!' || c == '@') {
+      errors.push(format!("irc.nick: expected a nick without spaces, got {:?}", irc.nick));
+    }
+    for channel in &irc.channels {
+      if !channel.starts_with(|c| c == '#' || c == '&') || channel.contains(|c :char| c.is_whitespace() || c == ',') {
+        errors.push(format!("irc.channels: expected channels like #coredump, got {:?}", channel));
+      }
+    }
+  }
+
+  if let Some(ref matrix) = config.matrix {
+    if !matrix.homeserver.starts_with("http://") && !matrix.homeserver.starts_with("https://") {
+      errors.push(format!("matrix.homeserver: expected an http(s) URL, got {:?}", matrix.homeserver));
+    }
+    if matrix.access_token.is_empty() {
+      errors.push("matrix.access_token: may not be empty".into());
+    }
+    for room in &matrix.rooms {
+      if !room.starts_with(|c| c == '#' || c == '!') || !room.contains(':') {
+        errors.push(format!("matrix.rooms: expected room ids or aliases like #coredump:matrix.org, got {:?}", room));
+      }
+    }
+  }
+
+  if let Some(ref webhook) = config.webhook {
+    if !webhook.url.starts_with("https://") {
+      errors.push(format!("webhook.url: expected an https URL, Telegram delivers nothing else, got {:?}", webhook.url));
+    }
+    if webhook.secret.is_empty() || !webhook.secret.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+      errors.push("webhook.secret: may only contain letters, digits, - and _".into());
+    }
+    if !is_address(&webhook.listen) {
+      errors.push(format!("webhook.listen: expected host:port, got {:?}", webhook.listen));
+    }
+  }
+
+  errors
+}
+
+/// `host:port`
+fn is_address(address :&str) -> bool {
+  let mut parts = address.rsplitn(2, ':');
+  let port = parts.next().unwrap_or("");
+  let host = parts.next().unwrap_or("");
+  !host.is_empty() && port.parse::<u16>().is_ok()
+}
+
+/// Reads the settings, the environment takes precedence over the file
+struct Reader<'a> {
+  table :Table,
+  env :&'a Fn(&str) -> Option<String>,
+  errors :Vec<String>,
+}
+
+impl<'a> Reader<'a> {
+  fn check_unknown(&mut self) {
+    for (section, value) in &self.table {
+      match value.as_table() {
+        Some(keys) => for key in keys.keys() {
+          if !SETTINGS.iter().any(|&(s, k, _)| s == section && k == key) {
+            self.errors.push(format!("{}.{}: unknown setting", section, key));
+          }
+        },
+        None => self.errors.push(format!("{}: unknown setting, the settings are grouped in sections like [telegram]", section)),
+      }
+    }
+  }
+
+  /// The value from the environment or else from the file
+  fn lookup(&self, section :&str, key :&str) -> Option<Result<(String, String), (String, Value)>> {
+    let var = SETTINGS.iter().find(|&&(s, k, _)| s == section && k == key).map(|&(_, _, var)| var).unwrap();
+    if let Some(value) = (self.env)(var) {
+      return Some(Ok((var.into(), value)));
+    }
+    self.table.get(section).and_then(|s| s.as_table()).and_then(|s| s.get(key))
+      .map(|value| Err((format!("{}.{}", section, key), value.clone())))
+  }
+
+  fn string(&mut self, section :&str, key :&str) -> Option<String> {
+    match self.lookup(section, key) {
+      None => None,
+      Some(Ok((_, value))) | Some(Err((_, Value::String(value)))) => Some(value),
+      Some(Err((name, value))) => self.wrong_type(name, "a string", &value),
+    }
+  }
+
+  fn integers(&mut self, section :&str, key :&str) -> Option<Vec<i64>> {
+    match self.lookup(section, key) {
+      None => None,
+      Some(Ok((var, value))) => {
+        let parsed :Result<Vec<i64>, _> = value.split(',').map(|v| v.trim()).filter(|v| !v.is_empty())
+          .map(|v| v.parse::<i64>()).collect();
+        match parsed {
+          Ok(integers) => Some(integers),
+          Err(_) => self.invalid(var, "integers separated by commas", &value),
+        }
+      },
+      Some(Err((name, value))) => {
+        let integers :Option<Vec<i64>> = value.as_slice().and_then(|values| values.iter().map(|v| v.as_integer()).collect());
+        match integers {
+          Some(integers) => Some(integers),
+          None => self.wrong_type(name, "an array of integers", &value),
+        }
+      },
+    }
+  }
+
+  fn strings(&mut self, section :&str, key :&str) -> Option<Vec<String>> {
+    match self.lookup(section, key) {
+      None => None,
+      Some(Ok((_, value))) => Some(value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()),
+      Some(Err((name, value))) => {
+        let strings :Option<Vec<String>> = value.as_slice()
+          .and_then(|values| values.iter().map(|v| v.as_str().map(String::from)).collect());
+        match strings {
+          Some(strings) => Some(strings),
+          None => self.wrong_type(name, "an array of strings", &value),
+        }
+      },
+    }
+  }
+
+  fn float(&mut self, section :&str, key :&str) -> Option<f64> {
+    match self.lookup(section, key) {
+      None => None,
+      Some(Ok((var, value))) => match value.trim().parse::<f64>() {
+        Ok(number) => Some(number),
+        Err(_) => self.invalid(var, "a number", &value),
+      },
+      Some(Err((_, Value::Float(number)))) => Some(number),
+      Some(Err((_, Value::Integer(number)))) => Some(number as f64),
+      Some(Err((name, value))) => self.wrong_type(name, "a number", &value),
+    }
+  }
+
+  /// A number of `unit`, e.g. "seconds"
+  fn unsigned(&mut self, section :&str, key :&str, unit :&str) -> Option<u64> {
+    let expected = format!("a number of {}", unit);
+    let number = match self.lookup(section, key) {
+      None => return None,
+      Some(Ok((var, value))) => match value.trim().parse::<i64>() {
+        Ok(number) => (var, number),
+        Err(_) => return self.invalid(var, &expected, &value),
+      },
+      Some(Err((name, Value::Integer(number)))) => (name, number),
+      Some(Err((name, value))) => return self.wrong_type(name, &expected, &value),
+    };
+
+    match number {
+      (name, number) if number < 0 => self.invalid(name, &expected, &number.to_string()),
+      (_, number) => Some(number as u64),
+    }
+  }
+
+  fn boolean(&mut self, section :&str, key :&str) -> Option<bool> {
+    match self.lookup(section, key) {
+      None => None,
+      Some(Ok((var, value))) => match value.trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => self.invalid(var, "true or false", &value),
+      },
+      Some(Err((_, Value::Boolean(enabled)))) => Some(enabled),
+      Some(Err((name, value))) => self.wrong_type(name, "true or false", &value),
+    }
+  }
+
+  fn wrong_type<T>(&mut self, name :String, expected :&str, value :&Value) -> Option<T> {
+    self.errors.push(format!("{}: expected {}, found {}", name, expected, value.type_str()));
+    None
+  }
+
+  fn invalid<T>(&mut self, name :String, expected :&str, value :&str) -> Option<T> {
+    self.errors.push(format!("{}: expected {}, got {:?}", name, expected, value));
+    None
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{Arrival, Config, Irc, Matrix, Webhook, check_reload, from_toml};
+  use std::path::PathBuf;
+
+  fn no_env(_ :&str) -> Option<String> {
+    None
+  }
+
+  #[test]
+  fn defaults() {
+    assert_eq!(Ok(Config::default()), from_toml("", no_env));
+  }
+
+  #[test]
+  fn file() {
+    let config = from_toml(r#"
+      [telegram]
+      token = "123456:ABC-DEF"
+      admins = [42, 43]
+
+      [space]
+      status_url = "https://status.example.org/"
+      trend_minutes = 30
+
+      [backoff]
+      max_seconds = 30
+
+      [storage]
+      data_dir = "/var/lib/coredumpbot"
+
+      [messages]
+      welcome = "Hi"
+
+      [features]
+      webcam = false
+    "#, no_env).unwrap();
+
+    assert_eq!(Some("123456:ABC-DEF".into()), config.token);
+    assert_eq!("CoreDumpBot", config.username);
+    assert!(config.is_admin(43));
+    assert!(!config.is_admin(44));
+    assert_eq!("https://status.example.org/", config.status_url);
+    assert_eq!(30, config.trend_minutes);
+    assert_eq!((1, 30), (config.min_backoff_seconds, config.max_backoff_seconds));
+    assert_eq!(PathBuf::from("/var/lib/coredumpbot"), config.data_dir);
+    assert_eq!("Hi", config.welcome_message);
+    assert!(!config.features.webcam);
+    assert!(config.features.news);
+  }
+
+  #[test]
+  fn frontends() {
+    let config = from_toml(r##"
+      [arrival]
+      radius_meters = 200
+      instructions = "Ring twice"
+
+      [irc]
+      server = "chat.freenode.net:6667"
+      channels = ["#coredump"]
+
+      [matrix]
+      homeserver = "https://matrix.org/"
+      access_token = "XXXXXXXX"
+
+      [webhook]
+      url = "https://bot.example.com"
+      secret = "s3cret"
+    "##, no_env).unwrap();
+
+    assert_eq!(Arrival{ radius_meters: 200.0, instructions: Some("Ring twice".into()) }, config.arrival);
+    assert_eq!(Some(Irc{ server: "chat.freenode.net:6667".into(), nick: "CoredumpBot".into(), channels: vec!["#coredump".into()] }),
+      config.irc);
+    assert_eq!(Some(Matrix{ homeserver: "https://matrix.org".into(), access_token: "XXXXXXXX".into(), rooms: vec![] }), config.matrix);
+    assert_eq!(Some(Webhook{ url: "https://bot.example.com".into(), listen: "0.0.0.0:8443".into(), secret: "s3cret".into(), tls: None }),
+      config.webhook);
+    assert_eq!("https://bot.example.com/s3cret", config.webhook.unwrap().endpoint());
+  }
+
+  #[test]
+  fn frontend_errors() {
+    assert_eq!(Err("irc.server: required for the other IRC settings\n\
+      matrix.homeserver, matrix.access_token: both are required for Matrix\n\
+      webhook.cert, webhook.key: have to be set together\n\
+      webhook.secret: required for the webhook\n\
+      arrival.radius_meters: expected a distance in meters, got -1".into()),
+      from_toml("[arrival]\nradius_meters = -1.0\n[irc]\nnick = \"bot\"\n[matrix]\nhomeserver = \"https://matrix.org\"\n\
+        [webhook]\nurl = \"https://bot.example.com\"\ncert = \"cert.pem\"\n", no_env).map(|_| ()));
+
+    assert_eq!(Err("irc.server: expected host:port, got \"chat.freenode.net\"\n\
+      irc.channels: expected channels like #coredump, got \"coredump\"".into()),
+      from_toml("", |var| match var {
+        "COREDUMP_BOT_IRC_SERVER" => Some("chat.freenode.net".into()),
+        "COREDUMP_BOT_IRC_CHANNELS" => Some("#coredump, coredump".into()),
+        _ => None,
+      }).map(|_| ()));
+  }
+
+  #[test]
+  fn environment_overrides_file() {
+    let config = from_toml("[telegram]\nusername = \"FileBot\"\nadmins = [1]\n[features]\nnews = true\n", |var| match var {
+      "TELEGRAM_BOT_TOKEN" => Some("1:env".into()),
+      "COREDUMP_BOT_ADMINS" => Some("7, 8".into()),
+      "COREDUMP_BOT_FEATURE_NEWS" => Some("false".into()),
+      _ => None,
+    }).unwrap();
+
+    assert_eq!(Some("1:env".into()), config.token);
+    assert_eq!("FileBot", config.username);
+    assert_eq!(vec![7, 8], config.admins);
+    assert!(!config.features.news);
+  }
+
+  #[test]
+  fn errors() {
+    assert_eq!(Err("line 2, column 6: expected `=`, but found `\n`".into()), from_toml("[telegram]\ntoken\n", no_env).map(|_| ()));
+
+    assert_eq!(Err("status_url: unknown setting, the settings are grouped in sections like [telegram]\n\
+      telegram.tokn: unknown setting\n\
+      backoff.min_seconds: expected a number of seconds, found string\n\
+      features.news: expected true or false, found integer".into()),
+      from_toml("status_url = \"https://example.org\"\n[telegram]\ntokn = \"1:a\"\n[backoff]\nmin_seconds = \"1\"\n[features]\nnews = 1\n", no_env).map(|_| ()));
+
+    assert_eq!(Err("COREDUMP_BOT_ADMINS: expected integers separated by commas, got \"42,alice\"".into()),
+      from_toml("", |var| if var == "COREDUMP_BOT_ADMINS" { Some("42,alice".into()) } else { None }).map(|_| ()));
+  }
+
+  #[test]
+  fn validation() {
+    assert_eq!(Err("telegram.token: expected a token like 123456:ABC-DEF from @BotFather\n\
+      telegram.username: expected the name of the bot without the @\n\
+      space.status_url: expected an http(s) URL, got \"status.crdmp.ch\"\n\
+      space.trend_minutes: has to be at least 1\n\
+      backoff.max_seconds: has to be at least min_seconds (10)\n\
+      storage.temp_dir_name: expected a single directory name, got \"../etc\"".into()),
+      from_toml("[telegram]\ntoken = \"secret\"\nusername = \"@CoreDumpBot\"\n\
+        [space]\nstatus_url = \"status.crdmp.ch\"\ntrend_minutes = 0\n[backoff]\nmin_seconds = 10\nmax_seconds = 5\n\
+        [storage]\ntemp_dir_name = \"../etc\"\n", no_env).map(|_| ()));
+  }
+
+  #[test]
+  fn restart_only_settings() {
+    let current = Config{ token: Some("1:a".into()), ..Config::default() };
+    assert_eq!(Ok(()), check_reload(&current, &Config{ welcome_message: "Hi".into(), ..current.clone() }));
+    assert_eq!(Err("irc: changing IRC needs a restart".into()), check_reload(&current, &Config{
+      irc: Some(Irc{ server: "chat.freenode.net:6667".into(), nick: "CoredumpBot".into(), channels: vec![] }),
+      ..current.clone()
+    }));
+    assert_eq!(Err("telegram.token: changing the token needs a restart\n\
+      storage.data_dir: changing the data directory needs a restart".into()),
+      check_reload(&current, &Config{ token: None, data_dir: PathBuf::from("/tmp"), ..current.clone() }));
+  }
+}
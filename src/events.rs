@@ -0,0 +1,93 @@
+//! Remind subscribed chats of upcoming events
+
+use calendar::Occurrence;
+use chrono::{DateTime, UTC, Duration};
+use storage;
+use subscriptions::{Subscribed, Subscriptions};
+use std::io;
+
+/// Number of reminded occurrences to remember
+const MAX_REMINDED :usize = 200;
+
+/// How long before the start of an event the reminder is sent
+pub const REMINDER_LEAD_MINUTES :i64 = 60;
+
+#[derive(RustcEncodable, RustcDecodable, Default)]
+struct EventsState {
+  subscribers :Subscriptions,
+  reminded :Vec<String>,
+}
+
+/// `default()` starts without subscriptions and is not stored until saved
+#[derive(Default)]
+pub struct Events {
+  state :EventsState,
+}
+
+impl Events {
+  /// Restore the subscriptions and sent reminders from the data directory
+  pub fn load() -> Events {
+    Events{
+      state: storage::load("events").unwrap_or(EventsState::default()),
+    }
+  }
+
+  /// Returns the occurrences starting within the reminder lead time which were not reminded yet
+  /// and remembers them as reminded.
+  pub fn take_due(&mut self, occurrences :&[Occurrence], now :&DateTime<UTC>) -> Vec<Occurrence> {
+    let lead = *now + Duration::minutes(REMINDER_LEAD_MINUTES);
+    let mut due = vec![];
+
+    for occurrence in occurrences {
+      if occurrence.all_day || occurrence.start <= *now || occurrence.start > lead {
+        continue;
+      }
+
+      let key = occurrence.key();
+      if !self.state.reminded.contains(&key) {
+        self.state.reminded.push(key);
+        due.push(occurrence.clone());
+      }
+    }
+
+    if self.state.reminded.len() > MAX_REMINDED {
+      let overflow = self.state.reminded.len() - MAX_REMINDED;
+      self.state.reminded.drain(..overflow);
+    }
+
+    due
+  }
+}
+
+impl Subscribed for Events {
+  fn subscriptions(&mut self) -> &mut Subscriptions {
+    &mut self.state.subscribers
+  }
+
+  fn save(&self) -> Result<(), io::Error> {
+    storage::save("events", &self.state)
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{Events, EventsState};
+  use calendar::Occurrence;
+  use chrono::{UTC, TimeZone};
+
+  fn occurrence(hour :u32, minute :u32) -> Occurrence {
+    Occurrence{ uid: "hackabend".into(), summary: "Hackabend".into(), location: None, start: UTC.ymd(2016, 10, 24).and_hms(hour, minute, 0), all_day: false }
+  }
+
+  #[test]
+  fn remind_once() {
+    let mut events = Events{ state: EventsState::default() };
+    let now = UTC.ymd(2016, 10, 24).and_hms(17, 0, 0);
+    let upcoming = vec![occurrence(17, 50), occurrence(18, 30)];
+
+    assert_eq!(vec![occurrence(17, 50)], events.take_due(&upcoming, &now));
+    assert_eq!(Vec::<Occurrence>::new(), events.take_due(&upcoming, &now));
+  }
+}
@@ -0,0 +1,240 @@
+//! Minimal reader for RSS 2.0 and Atom feeds
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FeedEntry {
+  pub id :String,
+  pub title :String,
+  pub link :String,
+}
+
+/// A raw XML element as found by `elements`
+struct Element<'a> {
+  attributes :&'a str,
+  content :&'a str,
+}
+
+/// Parse an RSS 2.0 or Atom document.
+/// Entries are returned in the order of the document, which is usually newest first.
+pub fn parse_feed(xml :&str) -> Vec<FeedEntry> {
+  let mut entries = vec![];
+
+  // RSS 2.0
+  for item in elements(xml, "item") {
+    let link = first_text(item.content, "link").unwrap_or(String::new());
+    entries.push( FeedEntry{
+      id: first_text(item.content, "guid").unwrap_or(link.clone()),
+      title: first_text(item.content, "title").unwrap_or(String::new()),
+      link: link,
+    });
+  }
+
+  if entries.is_empty() {
+    // Atom
+    for entry in elements(xml, "entry") {
+      let link = atom_link(entry.content).unwrap_or(String::new());
+      entries.push( FeedEntry{
+        id: first_text(entry.content, "id").unwrap_or(link.clone()),
+        title: first_text(entry.content, "title").unwrap_or(String::new()),
+        link: link,
+      });
+    }
+  }
+
+  entries
+}
+
+/// Atom allows several links per entry, prefer `rel="alternate"` or the one without rel
+fn atom_link(xml :&str) -> Option<String> {
+  let mut fallback = None;
+
+  for link in elements(xml, "link") {
+    let href = match attribute(link.attributes, "href") {
+      Some(href) => href,
+      None => continue,
+    };
+    match attribute(link.attributes, "rel") {
+      None => return Some(href),
+      Some(ref rel) if rel == "alternate" => return Some(href),
+      Some(_) => if fallback.is_none() { fallback = Some(href) },
+    }
+  }
+
+  fallback
+}
+
+fn first_text(xml :&str, tag :&str) -> Option<String> {
+  elements(xml, tag).into_iter().next().map(|e| decode_text(e.content))
+}
+
+/// Returns every `<tag …>…</tag>` and `<tag …/>` in `xml`, nesting of the same tag is not supported
+fn elements<'a>(xml :&'a str, tag :&str) -> Vec<Element<'a>> {
+  let open = format!("<{}", tag);
+  let close = format!("</{}>", tag);
+  let mut found = vec![];
+  let mut rest = xml;
+
+  while let Some(start) = rest.find(&*open) {
+    let after = &rest[start + open.len()..];
+
+    // <link> must not match <linkfoo>
+    match after.chars().next() {
+      Some('>') | Some('/') | Some(' ') | Some('\t') | Some('\r') | Some('\n') => {},
+      _ => {
+        rest = after;
+        continue;
+      },
+    }
+
+    let end_of_tag = match after.find('>') {
+      Some(p) => p,
+      None => break,
+    };
+    let attributes = &after[..end_of_tag];
+    let body = &after[end_of_tag+1..];
+
+    if attributes.ends_with('/') {
+      found.push( Element{ attributes: &attributes[..attributes.len()-1], content: "" } );
+      rest = body;
+      continue;
+    }
+
+    match body.find(&*close) {
+      Some(end) => {
+        found.push( Element{ attributes: attributes, content: &body[..end] } );
+        rest = &body[end + close.len()..];
+      },
+      None => break,
+    }
+  }
+
+  found
+}
+
+fn attribute(attributes :&str, name :&str) -> Option<String> {
+  let attributes = attributes.replace(|c| c == '\t' || c == '\r' || c == '\n', " ");
+
+  for quote in &['"', '\''] {
+    let needle = format!(" {}={}", name, quote);
+    if let Some(p) = attributes.find(&*needle) {
+      let value = &attributes[p + needle.len()..];
+      if let Some(end) = value.find(*quote) {
+        return Some( decode_text(&value[..end]) );
+      }
+    }
+  }
+
+  None
+}
+
+/// Strips CDATA sections and replaces XML entities
+pub fn decode_text(raw :&str) -> String {
+  let raw = raw.trim();
+
+  if raw.starts_with("<![CDATA[") && raw.ends_with("]]>") {
+    return raw[9..raw.len()-3].trim().into();
+  }
+
+  let mut text = String::new();
+  let mut rest = raw;
+
+  while let Some(p) = rest.find('&') {
+    text.push_str(&rest[..p]);
+    rest = &rest[p..];
+
+    let end = match rest.find(';') {
+      Some(end) if end < 10 => end,
+      _ => {
+        text.push('&');
+        rest = &rest[1..];
+        continue;
+      },
+    };
+
+    let decoded = match &rest[1..end] {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      entity if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32),
+      entity if entity.starts_with('#') => entity[1..].parse::<u32>().ok().and_then(::std::char::from_u32),
+      _ => None,
+    };
+
+    match decoded {
+      Some(c) => {
+        text.push(c);
+        rest = &rest[end+1..];
+      },
+      None => {
+        text.push('&');
+        rest = &rest[1..];
+      },
+    }
+  }
+  text.push_str(rest);
+
+  text
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{parse_feed, FeedEntry, decode_text};
+
+  #[test]
+  fn rss() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+  <title>Coredump</title>
+  <atom:link href="https://www.coredump.ch/feed/" rel="self" type="application/rss+xml" />
+  <link>https://www.coredump.ch</link>
+  <item>
+    <title>Lasercutter Workshop</title>
+    <link>https://www.coredump.ch/2016/10/lasercutter/</link>
+    <guid isPermaLink="false">https://www.coredump.ch/?p=1234</guid>
+  </item>
+  <item>
+    <title><![CDATA[Rust & Coffee]]></title>
+    <link>https://www.coredump.ch/2016/09/rust/</link>
+  </item>
+</channel>
+</rss>"#;
+
+    assert_eq!(vec![
+      FeedEntry{ id: "https://www.coredump.ch/?p=1234".into(), title: "Lasercutter Workshop".into(), link: "https://www.coredump.ch/2016/10/lasercutter/".into() },
+      FeedEntry{ id: "https://www.coredump.ch/2016/09/rust/".into(), title: "Rust & Coffee".into(), link: "https://www.coredump.ch/2016/09/rust/".into() },
+    ], parse_feed(xml));
+  }
+
+  #[test]
+  fn atom() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Coredump</title>
+  <link href="https://www.coredump.ch/"/>
+  <entry>
+    <title>Hackathon</title>
+    <link rel="replies" href="https://www.coredump.ch/2016/hackathon/#comments"/>
+    <link rel="alternate" href="https://www.coredump.ch/2016/hackathon/"/>
+    <id>tag:coredump.ch,2016:hackathon</id>
+  </entry>
+</feed>"#;
+
+    assert_eq!(vec![
+      FeedEntry{ id: "tag:coredump.ch,2016:hackathon".into(), title: "Hackathon".into(), link: "https://www.coredump.ch/2016/hackathon/".into() },
+    ], parse_feed(xml));
+  }
+
+  #[test]
+  fn empty() {
+    assert_eq!(Vec::<FeedEntry>::new(), parse_feed("<rss><channel></channel></rss>"));
+  }
+
+  #[test]
+  fn entities() {
+    assert_eq!("Tom & Jerry <3 \"Caf\u{e9}\" &unknown;", decode_text(" Tom &amp; Jerry &lt;3 &quot;Caf&#233;&#x22; &unknown; "));
+  }
+}
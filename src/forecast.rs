@@ -0,0 +1,179 @@
+//! Estimate when the space is open and how crowded it is from the recorded history
+
+use chrono::{UTC, Weekday, Datelike, Timelike, TimeZone};
+use history::Sample;
+use local_time;
+
+/// Width of the bar showing the probability of the space being open
+const BAR_WIDTH :usize = 10;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Cell {
+  /// Number of samples within this hour
+  pub samples :u32,
+  /// Samples which knew about `state.open`
+  pub with_state :u32,
+  /// Samples taken while the space was open
+  pub open :u32,
+  /// Sum of `people_now_present` over all samples
+  pub people :f64,
+}
+
+impl Cell {
+  pub fn open_probability(&self) -> Option<f64> {
+    if self.with_state == 0 {
+      None
+    } else {
+      Some(self.open as f64 / self.with_state as f64)
+    }
+  }
+
+  pub fn average_people(&self) -> Option<f64> {
+    if self.samples == 0 {
+      None
+    } else {
+      Some(self.people / self.samples as f64)
+    }
+  }
+}
+
+/// `people_now_present` samples grouped by local weekday and hour
+pub struct Heatmap {
+  cells :[[Cell; 24]; 7],
+}
+
+impl Heatmap {
+  pub fn from_samples(samples :&[Sample]) -> Heatmap {
+    let mut cells = [[Cell::default(); 24]; 7];
+
+    for sample in samples {
+      let local = local_time::to_local(&UTC.timestamp(sample.timestamp, 0));
+      let cell = &mut cells[local.weekday().num_days_from_monday() as usize][local.hour() as usize];
+
+      cell.samples += 1;
+      cell.people += sample.value;
+      if let Some(open) = sample.open {
+        cell.with_state += 1;
+        if open {
+          cell.open += 1;
+        }
+      }
+    }
+
+    Heatmap{ cells: cells }
+  }
+
+  pub fn cell(&self, weekday :Weekday, hour :u32) -> &Cell {
+    &self.cells[weekday.num_days_from_monday() as usize][hour as usize]
+  }
+
+  /// Hour by hour forecast for the weekday, hours without any samples are left out
+  pub fn describe_day(&self, weekday :Weekday) -> String {
+    let mut lines = vec![];
+    let mut busiest :Option<(u32, f64)> = None;
+
+    for hour in 0..24 {
+      let cell = self.cell(weekday, hour);
+      let people = match cell.average_people() {
+        Some(people) => people,
+        None => continue,
+      };
+      if busiest.map_or(true, |(_, most)| people > most) {
+        busiest = Some((hour, people));
+      }
+
+      let open = match cell.open_probability() {
+        Some(p) => format!("{} {:>3.0}% open", bar(p), p * 100.0),
+        None => format!("{} ?", bar(0.0)),
+      };
+      lines.push(format!("{:02}:00 {}, ~{:.0} people", hour, open, people));
+    }
+
+    match busiest {
+      None => format!("No history recorded for {} yet", weekday_name(weekday)),
+      Some((hour, people)) => {
+        let mut s = format!("Forecast for {}\n{}", weekday_name(weekday), lines.join("\n"));
+        if people > 0.0 {
+          s.push_str(&format!("\nUsually busiest around {:02}:00", hour));
+        }
+        s
+      },
+    }
+  }
+}
+
+fn bar(probability :f64) -> String {
+  let filled = (probability * BAR_WIDTH as f64).round() as usize;
+  let mut s = String::new();
+  for i in 0..BAR_WIDTH {
+    s.push(if i < filled { '█' } else { '░' });
+  }
+  s
+}
+
+pub fn weekday_name(weekday :Weekday) -> &'static str {
+  match weekday {
+    Weekday::Mon => "Monday",
+    Weekday::Tue => "Tuesday",
+    Weekday::Wed => "Wednesday",
+    Weekday::Thu => "Thursday",
+    Weekday::Fri => "Friday",
+    Weekday::Sat => "Saturday",
+    Weekday::Sun => "Sunday",
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{Heatmap, Cell, bar};
+  use history::Sample;
+  use chrono::Weekday;
+
+  // Mon 2016-11-07 19:30 UTC, 20:30 CET
+  const MONDAY_EVENING :i64 = 1478547000;
+
+  fn sample(timestamp :i64, people :f64, open :Option<bool>) -> Sample {
+    Sample{ timestamp: timestamp, sensor: "people_now_present".into(), index: 0, value: people, open: open }
+  }
+
+  #[test]
+  fn cells() {
+    let week = 7 * 24 * 60 * 60;
+    let heatmap = Heatmap::from_samples(&[
+      sample(MONDAY_EVENING, 6.0, Some(true)),
+      sample(MONDAY_EVENING + week, 0.0, Some(false)),
+      sample(MONDAY_EVENING + 2 * week, 3.0, None),
+    ]);
+
+    let cell = heatmap.cell(Weekday::Mon, 20);
+    assert_eq!(&Cell{ samples: 3, with_state: 2, open: 1, people: 9.0 }, cell);
+    assert_eq!(Some(0.5), cell.open_probability());
+    assert_eq!(Some(3.0), cell.average_people());
+
+    assert_eq!(None, heatmap.cell(Weekday::Mon, 21).open_probability());
+    assert_eq!(None, heatmap.cell(Weekday::Tue, 20).average_people());
+  }
+
+  #[test]
+  fn describe() {
+    let heatmap = Heatmap::from_samples(&[
+      sample(MONDAY_EVENING - 60 * 60, 2.0, Some(true)),
+      sample(MONDAY_EVENING, 6.0, Some(true)),
+    ]);
+
+    assert_eq!("Forecast for Monday\n\
+                19:00 ██████████ 100% open, ~2 people\n\
+                20:00 ██████████ 100% open, ~6 people\n\
+                Usually busiest around 20:00", heatmap.describe_day(Weekday::Mon));
+    assert_eq!("No history recorded for Tuesday yet", heatmap.describe_day(Weekday::Tue));
+  }
+
+  #[test]
+  fn bars() {
+    assert_eq!("░░░░░░░░░░", bar(0.0));
+    assert_eq!("███░░░░░░░", bar(0.33));
+    assert_eq!("██████████", bar(1.0));
+  }
+}
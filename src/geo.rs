@@ -0,0 +1,89 @@
+//! Distances and directions on the surface of the earth
+
+/// Mean radius of the earth in meters
+const EARTH_RADIUS :f64 = 6371008.8;
+
+const COMPASS_POINTS :[&'static str; 8] = ["north", "north-east", "east", "south-east", "south", "south-west", "west", "north-west"];
+
+/// Great-circle distance in meters (haversine formula)
+pub fn distance(from_lat :f64, from_lon :f64, to_lat :f64, to_lon :f64) -> f64 {
+  let d_lat = (to_lat - from_lat).to_radians();
+  let d_lon = (to_lon - from_lon).to_radians();
+
+  let a = (d_lat / 2.0).sin().powi(2)
+    + from_lat.to_radians().cos() * to_lat.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+
+  2.0 * EARTH_RADIUS * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Initial bearing in degrees clockwise from north, in the range [0, 360)
+pub fn bearing(from_lat :f64, from_lon :f64, to_lat :f64, to_lon :f64) -> f64 {
+  let from_lat = from_lat.to_radians();
+  let to_lat = to_lat.to_radians();
+  let d_lon = (to_lon - from_lon).to_radians();
+
+  let y = d_lon.sin() * to_lat.cos();
+  let x = from_lat.cos() * to_lat.sin() - from_lat.sin() * to_lat.cos() * d_lon.cos();
+
+  (y.atan2(x).to_degrees() + 360.0) % 360.0
+}
+
+/// Name of the nearest of the eight compass points
+pub fn compass_point(bearing :f64) -> &'static str {
+  let index = ((bearing + 22.5) / 45.0) as usize % COMPASS_POINTS.len();
+  COMPASS_POINTS[index]
+}
+
+/// Meters below one kilometer, kilometers with one decimal otherwise
+pub fn format_distance(meters :f64) -> String {
+  if meters < 1000.0 {
+    format!("{:.0} m", meters)
+  } else {
+    format!("{:.1} km", meters / 1000.0)
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{distance, bearing, compass_point, format_distance};
+
+  // Coredump, Rapperswil
+  const LAT :f64 = 47.22936;
+  const LON :f64 = 8.82949;
+
+  #[test]
+  fn distance_zurich_hb() {
+    let d = distance(47.37817, 8.54019, LAT, LON);
+    assert!(d > 27000.0 && d < 28000.0, "{}", d);
+  }
+
+  #[test]
+  fn distance_same_point() {
+    assert_eq!(0.0, distance(LAT, LON, LAT, LON));
+  }
+
+  #[test]
+  fn bearing_zurich_hb() {
+    // Rapperswil is south-east of Zürich
+    let b = bearing(47.37817, 8.54019, LAT, LON);
+    assert!(b > 120.0 && b < 135.0, "{}", b);
+    assert_eq!("south-east", compass_point(b));
+  }
+
+  #[test]
+  fn compass_points() {
+    assert_eq!("north", compass_point(0.0));
+    assert_eq!("north", compass_point(359.0));
+    assert_eq!("east", compass_point(90.0));
+    assert_eq!("south-west", compass_point(225.0));
+    assert_eq!("north-west", compass_point(337.0));
+  }
+
+  #[test]
+  fn format() {
+    assert_eq!("87 m", format_distance(87.2));
+    assert_eq!("27.4 km", format_distance(27412.0));
+  }
+}
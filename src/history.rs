@@ -0,0 +1,352 @@
+//! Record sensor values over time
+//!
+//! Samples are appended to a CSV file with one line per sensor and fetch:
+//! `timestamp,sensor,index,value,open`
+
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufReader, SeekFrom};
+use std::path::PathBuf;
+use std::time::Duration;
+use rustc_serialize::json;
+use storage;
+
+/// How much of the end of the file is read at once, see `read_all_since`
+const TAIL_CHUNK_BYTES :u64 = 64 * 1024;
+
+/// First line of exported CSV files
+const CSV_HEADER :&'static str = "timestamp,sensor,index,value,open";
+
+/// The sensors of the SpaceAPI status which are recorded, the others of the SensorSelector grammar are not
+pub const RECORDED_SENSORS :&'static [&'static str] = &["people_now_present", "temperature"];
+
+#[derive(Debug, PartialEq, Clone, RustcEncodable)]
+pub struct Sample {
+  /// Seconds since the unix epoch
+  pub timestamp :i64,
+  /// Name as in the SensorSelector grammar, e.g. "temperature"
+  pub sensor :String,
+  /// Position of the sensor within the sensors of the same kind
+  pub index :u64,
+  pub value :f64,
+  /// `state.open` of the status the value was taken from
+  pub open :Option<bool>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Statistics {
+  pub count :usize,
+  pub min :f64,
+  pub max :f64,
+  pub average :f64,
+  pub last :f64,
+}
+
+pub struct History {
+  path :PathBuf,
+}
+
+impl History {
+  /// The history in the data directory
+  pub fn open() -> History {
+    History::at(storage::data_dir().join("history.csv"))
+  }
+
+  pub fn at(path :PathBuf) -> History {
+    History{ path: path }
+  }
+
+  pub fn append(&self, samples :&[Sample]) -> Result<(), io::Error> {
+    if samples.is_empty() {
+      return Ok(());
+    }
+    if let Some(dir) = self.path.parent() {
+      try!(fs::create_dir_all(dir));
+    }
+
+    let mut lines = String::new();
+    for sample in samples {
+      lines.push_str(&format_line(sample));
+    }
+
+    let mut f = try!(OpenOptions::new().create(true).append(true).open(&self.path));
+    f.write_all(lines.as_bytes())
+  }
+
+  /// All samples of the sensor recorded at or after `since`, oldest first
+  pub fn read_since(&self, sensor :&str, index :u64, since :i64) -> Result<Vec<Sample>, io::Error> {
+    let samples = try!(self.read_all_since(since));
+    Ok(samples.into_iter().filter(|sample| sample.sensor == sensor && sample.index == index).collect())
+  }
+
+  /// Samples of all sensors recorded at or after `since`, oldest first.
+  /// The samples are appended in the order they were recorded, so only the end of the file is read.
+  pub fn read_all_since(&self, since :i64) -> Result<Vec<Sample>, io::Error> {
+    let mut f = match File::open(&self.path) {
+      Ok(f) => f,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+      Err(e) => return Err(e),
+    };
+
+    // Read chunks backwards until the first complete line is older than `since`
+    let mut start = try!(f.seek(SeekFrom::End(0)));
+    let mut tail :Vec<u8> = vec![];
+    while start > 0 {
+      let end = start;
+      start = end.saturating_sub(TAIL_CHUNK_BYTES);
+      let mut chunk = vec![0; (end - start) as usize];
+      try!(f.seek(SeekFrom::Start(start)));
+      try!(f.read_exact(&mut chunk));
+      chunk.extend(tail);
+      tail = chunk;
+
+      let first = complete_lines(&tail, start).split(|b| *b == b'\n').next()
+        .and_then(|line| parse_line(&String::from_utf8_lossy(line)));
+      if first.map_or(false, |sample| sample.timestamp < since) {
+        break;
+      }
+    }
+
+    let mut samples = vec![];
+    for line in String::from_utf8_lossy(complete_lines(&tail, start)).lines() {
+      match parse_line(line) {
+        Some(sample) => if sample.timestamp >= since {
+          samples.push(sample);
+        },
+        None => warn!("history: skipping invalid line {:?}", line),
+      }
+    }
+
+    Ok(samples)
+  }
+
+  /// Remove the samples recorded before `before`, returns how many were removed.
+  /// The file is replaced as a whole, so samples may not be appended meanwhile.
+  pub fn prune(&self, before :i64) -> Result<usize, io::Error> {
+    let f = match File::open(&self.path) {
+      Ok(f) => f,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+      Err(e) => return Err(e),
+    };
+
+    let mut kept = String::new();
+    let mut removed = 0;
+    for line in BufReader::new(f).lines() {
+      let line = try!(line);
+      match parse_line(&line) {
+        Some(ref sample) if sample.timestamp >= before => kept.push_str(&format_line(sample)),
+        _ => removed += 1,
+      }
+    }
+
+    if removed > 0 {
+      try!(storage::replace_file(&self.path, |tmp_path| File::create(tmp_path).and_then(|mut f| f.write_all(kept.as_bytes()))));
+    }
+    Ok(removed)
+  }
+}
+
+/// The lines of `tail` without the first one if it was cut, `start` is the position of `tail` in the file
+fn complete_lines(tail :&[u8], start :u64) -> &[u8] {
+  if start == 0 {
+    return tail;
+  }
+  match tail.iter().position(|b| *b == b'\n') {
+    Some(newline) => &tail[newline + 1..],
+    None => &[],
+  }
+}
+
+fn format_line(sample :&Sample) -> String {
+  let open = match sample.open {
+    Some(true) => "1",
+    Some(false) => "0",
+    None => "",
+  };
+  format!("{},{},{},{},{}\n", sample.timestamp, sample.sensor, sample.index, sample.value, open)
+}
+
+fn parse_line(line :&str) -> Option<Sample> {
+  let fields :Vec<&str> = line.split(',').collect();
+  if fields.len() != 5 {
+    return None;
+  }
+
+  let open = match fields[4] {
+    "1" => Some(true),
+    "0" => Some(false),
+    _ => None,
+  };
+
+  match (fields[0].parse(), fields[2].parse(), fields[3].parse()) {
+    (Ok(timestamp), Ok(index), Ok(value)) => Some( Sample{
+      timestamp: timestamp,
+      sensor: fields[1].into(),
+      index: index,
+      value: value,
+      open: open,
+    }),
+    _ => None,
+  }
+}
+
+pub fn is_recorded(sensor :&str) -> bool {
+  RECORDED_SENSORS.contains(&sensor)
+}
+
+/// Same format as the history file, with a header line
+pub fn to_csv(samples :&[Sample]) -> String {
+  let mut s = format!("{}\n", CSV_HEADER);
+  for sample in samples {
+    s.push_str(&format_line(sample));
+  }
+  s
+}
+
+/// Array of objects, `open` is null if unknown
+pub fn to_json(samples :&[Sample]) -> String {
+  json::encode(&samples).unwrap_or_else(|e| {
+    warn!("history::to_json unable to encode: {:?}", e);
+    "[]".into()
+  })
+}
+
+/// Returns None for an empty list of samples
+pub fn statistics(samples :&[Sample]) -> Option<Statistics> {
+  let last = match samples.last() {
+    Some(last) => last.value,
+    None => return None,
+  };
+
+  let mut min = ::std::f64::INFINITY;
+  let mut max = ::std::f64::NEG_INFINITY;
+  let mut sum = 0.0;
+  for sample in samples {
+    min = min.min(sample.value);
+    max = max.max(sample.value);
+    sum += sample.value;
+  }
+
+  Some( Statistics{
+    count: samples.len(),
+    min: min,
+    max: max,
+    average: sum / samples.len() as f64,
+    last: last,
+  })
+}
+
+/// Short form like the Duration grammar, e.g. "90min", "2h" or "7d"
+pub fn describe_duration(duration :&Duration) -> String {
+  let seconds = duration.as_secs();
+
+  if seconds % (60*60*24) == 0 && seconds > 0 {
+    format!("{}d", seconds / (60*60*24))
+  } else if seconds % (60*60) == 0 && seconds > 0 {
+    format!("{}h", seconds / (60*60))
+  } else {
+    format!("{}min", seconds / 60)
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{History, Sample, Statistics, statistics, describe_duration, is_recorded, to_csv, to_json};
+  use std::env;
+  use std::fs;
+  use std::time::Duration;
+
+  fn sample(timestamp :i64, sensor :&str, value :f64) -> Sample {
+    Sample{ timestamp: timestamp, sensor: sensor.into(), index: 0, value: value, open: Some(value > 0.0) }
+  }
+
+  #[test]
+  fn append_and_read() {
+    let path = env::temp_dir().join("coredump_bot").join("test_history").join("append_and_read.csv");
+    let _ = fs::remove_file(&path);
+    let history = History::at(path.clone());
+
+    history.append(&[sample(100, "people_now_present", 0.0), sample(100, "temperature", 55.7)]).unwrap();
+    history.append(&[sample(200, "people_now_present", 6.0), sample(200, "temperature", 48.7)]).unwrap();
+
+    assert_eq!(vec![sample(200, "temperature", 48.7)], history.read_since("temperature", 0, 150).unwrap());
+    assert_eq!(vec![sample(100, "people_now_present", 0.0), sample(200, "people_now_present", 6.0)],
+      history.read_since("people_now_present", 0, 0).unwrap());
+    assert_eq!(Vec::<Sample>::new(), history.read_since("people_now_present", 1, 0).unwrap());
+    assert_eq!(vec![sample(200, "people_now_present", 6.0), sample(200, "temperature", 48.7)], history.read_all_since(200).unwrap());
+
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn read_tail() {
+    let path = env::temp_dir().join("coredump_bot").join("test_history").join("tail.csv");
+    let _ = fs::remove_file(&path);
+    let history = History::at(path.clone());
+
+    // Several chunks, the lines are cut at their boundaries
+    let samples :Vec<Sample> = (0..5000).map(|t| sample(t, "temperature", t as f64 / 10.0)).collect();
+    history.append(&samples).unwrap();
+
+    assert_eq!(samples[4990..].to_vec(), history.read_all_since(4990).unwrap());
+    assert_eq!(samples[2000..].to_vec(), history.read_since("temperature", 0, 2000).unwrap());
+    assert_eq!(samples, history.read_all_since(0).unwrap());
+    assert_eq!(Vec::<Sample>::new(), history.read_all_since(5000).unwrap());
+
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn prune() {
+    let path = env::temp_dir().join("coredump_bot").join("test_history").join("prune.csv");
+    let _ = fs::remove_file(&path);
+    let history = History::at(path.clone());
+
+    assert_eq!(0, history.prune(150).unwrap());
+    history.append(&[sample(100, "people_now_present", 5.0), sample(100, "temperature", 51.2)]).unwrap();
+    history.append(&[sample(200, "people_now_present", 6.0)]).unwrap();
+
+    assert_eq!(2, history.prune(150).unwrap());
+    assert_eq!(0, history.prune(150).unwrap());
+    assert_eq!(vec![sample(200, "people_now_present", 6.0)], history.read_all_since(0).unwrap());
+
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn recorded() {
+    assert!(is_recorded("temperature"));
+    assert!(!is_recorded("humidity"));
+  }
+
+  #[test]
+  fn missing_file() {
+    let history = History::at(env::temp_dir().join("coredump_bot").join("test_history").join("missing.csv"));
+    assert_eq!(Vec::<Sample>::new(), history.read_since("temperature", 0, 0).unwrap());
+  }
+
+  #[test]
+  fn stats() {
+    let samples = vec![sample(1, "temperature", 50.0), sample(2, "temperature", 56.0), sample(3, "temperature", 47.0)];
+    assert_eq!(Some(Statistics{ count: 3, min: 47.0, max: 56.0, average: 51.0, last: 47.0 }), statistics(&samples));
+    assert_eq!(None, statistics(&[]));
+  }
+
+  #[test]
+  fn export() {
+    let samples = vec![sample(100, "temperature", 55.7), Sample{ open: None, ..sample(200, "temperature", 0.0) }];
+    assert_eq!("timestamp,sensor,index,value,open\n100,temperature,0,55.7,1\n200,temperature,0,0,\n", to_csv(&samples));
+    assert_eq!("[{\"timestamp\":100,\"sensor\":\"temperature\",\"index\":0,\"value\":55.7,\"open\":true},\
+                {\"timestamp\":200,\"sensor\":\"temperature\",\"index\":0,\"value\":0.0,\"open\":null}]", to_json(&samples));
+  }
+  
+  #[test]
+  fn durations() {
+    assert_eq!("10min", describe_duration(&Duration::from_secs(10*60)));
+    assert_eq!("90min", describe_duration(&Duration::from_secs(90*60)));
+    assert_eq!("2h", describe_duration(&Duration::from_secs(2*60*60)));
+    assert_eq!("7d", describe_duration(&Duration::from_secs(7*24*60*60)));
+  }
+}
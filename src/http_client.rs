@@ -0,0 +1,198 @@
+//! HTTP client with timeouts, connection reuse and conditional requests
+//!
+//! The client may be shared between threads, requests do not block each other.
+
+use hyper::Client;
+use hyper::client::pool::{Pool, Config};
+use hyper::header::{ETag, LastModified, IfNoneMatch, IfModifiedSince, EntityTag, HttpDate};
+use hyper::net::{NetworkConnector, HttpStream, HttpsConnector, Openssl};
+use hyper::status::StatusCode;
+use hyper;
+use spaceapi_client::Error;
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT_SECONDS :u64 = 5;
+const READ_TIMEOUT_SECONDS :u64 = 15;
+const WRITE_TIMEOUT_SECONDS :u64 = 15;
+/// Idle connections kept open per host
+const MAX_IDLE_CONNECTIONS :usize = 2;
+
+pub struct Response {
+  pub body :Vec<u8>,
+  /// false if the server answered 304 Not Modified and the body is the cached one
+  pub modified :bool,
+}
+
+/// Validators and body of the last response of an URL
+struct Cached {
+  etag :Option<EntityTag>,
+  last_modified :Option<HttpDate>,
+  body :Vec<u8>,
+}
+
+pub struct HttpClient {
+  client :Client,
+  cache :Mutex<HashMap<String, Cached>>,
+}
+
+/// A plain hyper client which gives up on unresponsive servers and reuses connections
+pub fn with_timeouts() -> Client {
+  let connector = HttpsConnector::with_connector(Openssl::default(), TimeoutConnector{
+    timeout: Duration::from_secs(CONNECT_TIMEOUT_SECONDS),
+  });
+  let mut client = Client::with_connector(Pool::with_connector(Config{ max_idle: MAX_IDLE_CONNECTIONS }, connector));
+  client.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECONDS)));
+  client.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECONDS)));
+  client
+}
+
+impl HttpClient {
+  pub fn new() -> HttpClient {
+    HttpClient{
+      client: with_timeouts(),
+      cache: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// GET the url, revalidating the previous response with If-None-Match and If-Modified-Since
+  pub fn get(&self, url :&str) -> Result<Response, Error> {
+    let (etag, last_modified) = match self.cache.lock().unwrap().get(url) {
+      Some(cached) => (cached.etag.clone(), cached.last_modified),
+      None => (None, None),
+    };
+
+    let mut request = self.client.get(url);
+    if let Some(etag) = etag {
+      request = request.header(IfNoneMatch::Items(vec![etag]));
+    }
+    if let Some(last_modified) = last_modified {
+      request = request.header(IfModifiedSince(last_modified));
+    }
+
+    let mut res = try!(request.send().map_err(|e| Error::Network(e.to_string())));
+
+    if res.status == StatusCode::NotModified {
+      if let Some(cached) = self.cache.lock().unwrap().get(url) {
+        return Ok( Response{ body: cached.body.clone(), modified: false } );
+      }
+    }
+    if !res.status.is_success() {
+      return Err(Error::HttpStatus(res.status));
+    }
+
+    let mut body = vec![];
+    try!(res.read_to_end(&mut body).map_err(|e| Error::Network(e.to_string())));
+
+    let etag = res.headers.get::<ETag>().map(|etag| etag.0.clone());
+    let last_modified = res.headers.get::<LastModified>().map(|last_modified| last_modified.0);
+    let mut cache = self.cache.lock().unwrap();
+    if etag.is_some() || last_modified.is_some() {
+      cache.insert(url.into(), Cached{ etag: etag, last_modified: last_modified, body: body.clone() });
+    } else {
+      cache.remove(url);
+    }
+
+    Ok( Response{ body: body, modified: true } )
+  }
+
+  /// GET a text document like the status or a feed
+  pub fn get_text(&self, url :&str) -> Result<String, Error> {
+    let response = try!(self.get(url));
+    String::from_utf8(response.body).map_err(|e| Error::Decode(format!("{} is not valid UTF-8: {}", url, e)))
+  }
+}
+
+/// Like hyper's HttpConnector, but gives up connecting after `timeout`
+struct TimeoutConnector {
+  timeout :Duration,
+}
+
+impl NetworkConnector for TimeoutConnector {
+  type Stream = HttpStream;
+
+  fn connect(&self, host :&str, port :u16, scheme :&str) -> hyper::Result<HttpStream> {
+    if scheme != "http" && scheme != "https" {
+      return Err(hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "Invalid scheme for Http")));
+    }
+
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("no address found for {}", host));
+    for address in try!((host, port).to_socket_addrs()) {
+      match TcpStream::connect_timeout(&address, self.timeout) {
+        Ok(stream) => return Ok(HttpStream(stream)),
+        Err(e) => last_error = e,
+      }
+    }
+    Err(hyper::Error::Io(last_error))
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::HttpClient;
+  use hyper::status::StatusCode;
+  use spaceapi_client::Error;
+  use std::io::prelude::*;
+  use std::net::TcpListener;
+  use std::thread;
+
+  /// Answers with ETag "v1" and 304 if the request carries it
+  fn serve(listener :TcpListener, requests :usize) {
+    for stream in listener.incoming().take(requests) {
+      let mut stream = stream.unwrap();
+      let mut request = vec![];
+      let mut buf = [0; 1024];
+      while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+        let n = stream.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..n]);
+      }
+
+      let request = String::from_utf8_lossy(&request).to_lowercase();
+      let response = if request.starts_with("get /missing ") {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+      } else if request.contains("if-none-match: \"v1\"") {
+        "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+      } else {
+        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello"
+      };
+      stream.write_all(response.as_bytes()).unwrap();
+    }
+  }
+
+  #[test]
+  fn not_modified() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/status", listener.local_addr().unwrap());
+    let server = thread::spawn(move || serve(listener, 2));
+
+    let client = HttpClient::new();
+    let first = client.get(&url).unwrap();
+    assert_eq!((b"hello".to_vec(), true), (first.body, first.modified));
+    let second = client.get(&url).unwrap();
+    assert_eq!((b"hello".to_vec(), false), (second.body, second.modified));
+
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn http_status() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/missing", listener.local_addr().unwrap());
+    let server = thread::spawn(move || serve(listener, 1));
+
+    match HttpClient::new().get(&url) {
+      Err(Error::HttpStatus(StatusCode::NotFound)) => (),
+      Err(e) => panic!("unexpected error {:?}", e),
+      Ok(_) => panic!("expected an error"),
+    }
+
+    server.join().unwrap();
+  }
+}
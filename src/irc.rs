@@ -0,0 +1,368 @@
+//! IRC frontend answering `!status`, `!location` and `!webcam` in the configured channels
+//!
+//! Speaks just enough of RFC 1459 to register, join, answer PINGs and messages.
+//! IRC can not show pictures, webcams are answered with their links.
+
+use commands::{Context, Handler, Reply, local_chat_id};
+use config as settings;
+use user_input_compiler::Input;
+
+use std::cmp;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+/// A line including the prefix added by the server may not exceed 512 bytes
+const MAX_MESSAGE_BYTES :usize = 400;
+/// Longer replies are cut off, channels should not be flooded
+const MAX_REPLY_LINES :usize = 8;
+/// The server is PINGed after this long without a line, the connection is dropped if the PING is not answered as long
+const IDLE_SECONDS :u64 = 120;
+
+/// Stay connected to the server, reconnecting with a growing delay within the configured bounds
+pub fn run(config :&settings::Irc, handler :&Handler) {
+  let mut backoff_seconds = settings::get().min_backoff_seconds;
+  let idle = Duration::from_secs(IDLE_SECONDS);
+
+  loop {
+    match TcpStream::connect(&*config.server).and_then(|stream| session(config, handler, stream, idle)) {
+      Ok(()) => {
+        warn!("IRC connection to {} closed", config.server);
+        backoff_seconds = settings::get().min_backoff_seconds;
+      },
+      Err(e) => {
+        warn!("IRC connection to {} failed: {}", config.server, e);
+        backoff_seconds = cmp::min(backoff_seconds * 2, settings::get().max_backoff_seconds);
+      },
+    }
+
+    thread::sleep(Duration::from_secs(backoff_seconds));
+  }
+}
+
+/// Register, join the channels and answer until the server closes the connection.
+/// The server is PINGed after `idle` without a line and the connection fails if it does not answer within another `idle`.
+pub fn session(config :&settings::Irc, handler :&Handler, stream :TcpStream, idle :Duration) -> io::Result<()> {
+  let writer = Arc::new(Mutex::new(try!(stream.try_clone())));
+  let nick = config.nick.clone();
+
+  try!(send(&writer, &format!("NICK {}", nick)));
+  try!(send(&writer, &format!("USER {} 0 * :Coredump SpaceAPI bot", nick)));
+
+  // PINGs are answered by the reading thread, so they are answered while a command is handled
+  let (lines_tx, lines) = mpsc::channel();
+  {
+    let writer = writer.clone();
+    thread::spawn(move || if let Err(e) = receive(stream, &writer, idle, &lines_tx) {
+      let _ = lines_tx.send(Err(e));
+    });
+  }
+
+  let result = handle_lines(config, handler, &writer, nick, lines);
+
+  // Ends the reading thread
+  let _ = writer.lock().unwrap().shutdown(Shutdown::Both);
+  result
+}
+
+/// Join the channels once registered and answer the commands
+fn handle_lines(config :&settings::Irc, handler :&Handler, writer :&Mutex<TcpStream>, mut nick :String,
+    lines :mpsc::Receiver<io::Result<String>>) -> io::Result<()> {
+  for line in lines {
+    let line = try!(line);
+    let message = match parse_line(&line) {
+      Some(message) => message,
+      None => continue,
+    };
+
+    match message.command {
+      // Welcome, registration is done
+      "001" => for channel in &config.channels {
+        try!(send(writer, &format!("JOIN {}", channel)));
+      },
+      // Nickname is already in use
+      "433" => {
+        nick.push('_');
+        try!(send(writer, &format!("NICK {}", nick)));
+      },
+      "PRIVMSG" if message.params.len() == 2 => {
+        let sender = message.prefix.and_then(|p| p.split('!').next()).unwrap_or("");
+        let (target, text) = (message.params[0], message.params[1]);
+        let private = same_nick(target, &nick);
+        let reply_to = if private { sender } else { target };
+
+        for line in answer(handler, reply_to, private, text) {
+          try!(send(writer, &format!("PRIVMSG {} :{}", reply_to, line)));
+        }
+      },
+      _ => (),
+    }
+  }
+
+  Ok(())
+}
+
+/// Pass the lines of the server to `lines` and answer its PINGs until it closes the connection
+fn receive(stream :TcpStream, writer :&Mutex<TcpStream>, idle :Duration, lines :&mpsc::Sender<io::Result<String>>) -> io::Result<()> {
+  try!(stream.set_read_timeout(Some(idle)));
+  let mut reader = BufReader::new(stream);
+  // Kept on a timeout, it may hold the beginning of a line
+  let mut line = vec![];
+  let mut pinged = false;
+
+  loop {
+    match reader.read_until(b'\n', &mut line) {
+      Ok(0) => return Ok(()),
+      Ok(_) => {
+        pinged = false;
+        let text = String::from_utf8_lossy(&line).into_owned();
+        line.clear();
+
+        match parse_line(&text) {
+          Some(ref message) if message.command == "PING" =>
+            try!(send(writer, &format!("PONG :{}", message.params.get(0).unwrap_or(&"")))),
+          Some(_) => if lines.send(Ok(text.clone())).is_err() {
+            return Ok(());
+          },
+          None => (),
+        }
+      },
+      Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+        if pinged {
+          return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer to PING"));
+        }
+        try!(send(writer, "PING :coredump_bot"));
+        pinged = true;
+      },
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+/// Nicks are compared case-insensitively (RFC 1459)
+fn same_nick(a :&str, b :&str) -> bool {
+  lower(a) == lower(b)
+}
+
+/// Nicks and channels in lower case, `{}|^` are the lower case of `[]\~`
+fn lower(name :&str) -> String {
+  name.to_lowercase().replace('[', "{").replace(']', "}").replace('\\', "|").replace('~', "^")
+}
+
+/// The lines to send for a message in the channel or the private conversation `conversation`, commands start with `!`
+fn answer(handler :&Handler, conversation :&str, private :bool, text :&str) -> Vec<String> {
+  let text = text.trim();
+  if !text.starts_with('!') {
+    return vec![];
+  }
+  info!("IRC: {}", text);
+
+  let context = Context{ chat_id: local_chat_id("irc", &lower(conversation)), private: private, pictures: false, user_id: None };
+  let replies = match Input::from(format!("/{}", &text[1..])) {
+    input @ Input::Status | input @ Input::Location | input @ Input::LocationText | input @ Input::WebCam{ .. } =>
+      handler.handle(&context, input),
+    _ if private => vec![Reply::Text("Try !status, !location or !webcam".into())],
+    _ => vec![],
+  };
+
+  let mut lines :Vec<String> = replies.into_iter()
+    .flat_map(|reply| {
+      let text = match reply {
+        Reply::Text(text) | Reply::Markdown(text) => text,
+        Reply::Venue{ text, .. } => text,
+        Reply::Photo{ caption, .. } => caption,
+        Reply::Document(path) => path.to_string_lossy().into_owned(),
+      };
+      text.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect::<Vec<_>>()
+    })
+    .map(|line| truncate(line, MAX_MESSAGE_BYTES))
+    .collect();
+
+  if lines.len() > MAX_REPLY_LINES {
+    lines.truncate(MAX_REPLY_LINES - 1);
+    lines.push("…".into());
+  }
+  lines
+}
+
+fn send(writer :&Mutex<TcpStream>, line :&str) -> io::Result<()> {
+  debug!("IRC > {}", line);
+  writer.lock().unwrap().write_all(format!("{}\r\n", line).as_bytes())
+}
+
+/// Cut the line at a character boundary
+fn truncate(mut line :String, max_bytes :usize) -> String {
+  if line.len() > max_bytes {
+    let mut end = max_bytes;
+    while !line.is_char_boundary(end) {
+      end -= 1;
+    }
+    line.truncate(end);
+  }
+  line
+}
+
+#[derive(Debug, PartialEq)]
+struct IrcMessage<'a> {
+  prefix :Option<&'a str>,
+  command :&'a str,
+  params :Vec<&'a str>,
+}
+
+/// `[:prefix] command {param} [:trailing]`
+fn parse_line<'a>(line :&'a str) -> Option<IrcMessage<'a>> {
+  let mut rest = line.trim_right_matches(|c| c == '\r' || c == '\n');
+
+  let prefix = if rest.starts_with(':') {
+    let end = match rest.find(' ') {
+      Some(end) => end,
+      None => return None,
+    };
+    let prefix = &rest[1..end];
+    rest = rest[end..].trim_left();
+    Some(prefix)
+  } else {
+    None
+  };
+
+  let (middle, trailing) = match rest.find(" :") {
+    Some(p) => (&rest[..p], Some(&rest[p+2..])),
+    None => (rest, None),
+  };
+  let mut words = middle.split(' ').filter(|w| !w.is_empty());
+  let command = match words.next() {
+    Some(command) => command,
+    None => return None,
+  };
+
+  let mut params :Vec<&str> = words.collect();
+  params.extend(trailing);
+
+  Some( IrcMessage{ prefix: prefix, command: command, params: params } )
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{IrcMessage, parse_line, same_nick, session, truncate};
+  use config::Irc;
+  use commands::fixture::FixtureHandler;
+  use std::io::prelude::*;
+  use std::io::BufReader;
+  use std::net::{TcpListener, TcpStream};
+  use std::thread;
+  use std::time::Duration;
+
+  #[test]
+  fn parse() {
+    assert_eq!(Some(IrcMessage{ prefix: None, command: "PING", params: vec!["irc.example.com"] }),
+      parse_line("PING :irc.example.com\r\n"));
+    assert_eq!(Some(IrcMessage{ prefix: Some("alice!a@example.com"), command: "PRIVMSG", params: vec!["#coredump", "!webcam 1"] }),
+      parse_line(":alice!a@example.com PRIVMSG #coredump :!webcam 1"));
+    assert_eq!(Some(IrcMessage{ prefix: Some("irc.example.com"), command: "001", params: vec!["CoredumpBot", "Welcome"] }),
+      parse_line(":irc.example.com 001 CoredumpBot :Welcome"));
+    assert_eq!(None, parse_line(":irc.example.com"));
+  }
+
+  #[test]
+  fn nicks() {
+    assert!(same_nick("coredumpbot_", "CoredumpBot_"));
+    assert!(same_nick("Bot{away}|^", "bot[AWAY]\\~"));
+    assert!(!same_nick("CoredumpBot", "CoredumpBot_"));
+  }
+
+  #[test]
+  fn truncate_at_char_boundary() {
+    assert_eq!("ab", truncate("abc".into(), 2));
+    assert_eq!("a", truncate("aöb".into(), 2));
+    assert_eq!("abc", truncate("abc".into(), 5));
+  }
+
+  /// A server stand-in which plays the conversation and returns what the bot sent
+  fn serve(listener :TcpListener, script :Vec<&'static str>) -> Vec<String> {
+    let (stream, _) = listener.accept().unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut received = vec![];
+
+    for step in script {
+      if step.starts_with("< ") {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(&step[2..], line.trim_right());
+        received.push(line.trim_right().to_string());
+      } else {
+        writer.write_all(format!("{}\r\n", step).as_bytes()).unwrap();
+      }
+    }
+    received
+  }
+
+  #[test]
+  fn conversation() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = Irc{
+      server: listener.local_addr().unwrap().to_string(),
+      nick: "CoredumpBot".into(),
+      channels: vec!["#coredump".into()],
+    };
+    let server = thread::spawn(move || serve(listener, vec![
+      "< NICK CoredumpBot",
+      "< USER CoredumpBot 0 * :Coredump SpaceAPI bot",
+      ":irc.example.com 433 * CoredumpBot :Nickname is already in use",
+      "< NICK CoredumpBot_",
+      ":irc.example.com 001 CoredumpBot_ :Welcome",
+      "< JOIN #coredump",
+      "PING :irc.example.com",
+      "< PONG :irc.example.com",
+      ":alice!a@example.com PRIVMSG #coredump :hello everybody",
+      ":alice!a@example.com PRIVMSG #coredump :!news",
+      ":alice!a@example.com PRIVMSG #coredump :!webcam 1",
+      "< PRIVMSG #coredump :ultimaker.jpg",
+      ":alice!a@example.com PRIVMSG #coredump :!location",
+      "< PRIVMSG #coredump :coredump",
+      "< PRIVMSG #coredump :Rapperswil",
+      ":alice!a@example.com PRIVMSG coredumpbot_ :!news",
+      "< PRIVMSG alice :Try !status, !location or !webcam",
+    ]));
+
+    let stream = TcpStream::connect(&*config.server).unwrap();
+    let client = thread::spawn(move || session(&config, &FixtureHandler, stream, Duration::from_secs(10)));
+
+    server.join().unwrap();
+    client.join().unwrap().unwrap();
+  }
+
+  #[test]
+  fn ping_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = Irc{
+      server: listener.local_addr().unwrap().to_string(),
+      nick: "CoredumpBot".into(),
+      channels: vec![],
+    };
+    let server = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut reader = BufReader::new(stream.try_clone().unwrap());
+      let mut received = vec![];
+      let mut line = String::new();
+      // Silent, but the connection stays open until the bot drops it
+      while reader.read_line(&mut line).unwrap() > 0 {
+        received.push(line.trim_right().to_string());
+        line.clear();
+      }
+      received
+    });
+
+    let stream = TcpStream::connect(&*config.server).unwrap();
+    let result = session(&config, &FixtureHandler, stream, Duration::from_millis(100));
+
+    assert_eq!("no answer to PING", result.unwrap_err().to_string());
+    assert_eq!(vec!["NICK CoredumpBot", "USER CoredumpBot 0 * :Coredump SpaceAPI bot", "PING :coredump_bot"],
+      server.join().unwrap());
+  }
+}
@@ -0,0 +1,86 @@
+//! Wall clock time of the hackerspace
+//!
+//! Central European Time with the EU daylight saving rules (Europe/Zurich).
+
+use chrono::{DateTime, UTC, FixedOffset, NaiveDate, NaiveDateTime, Datelike, Weekday, Duration, TimeZone};
+
+/// Offset of CET in seconds
+const WINTER :i32 = 60 * 60;
+/// Offset of CEST in seconds
+const SUMMER :i32 = 2 * 60 * 60;
+
+/// Offset of the space's timezone at the given instant
+pub fn offset_at(utc :&DateTime<UTC>) -> FixedOffset {
+  // Summer time starts and ends at 01:00 UTC on the last Sunday of March and October
+  let begin = UTC.from_utc_datetime(&last_sunday(utc.year(), 3).and_hms(1, 0, 0));
+  let end = UTC.from_utc_datetime(&last_sunday(utc.year(), 10).and_hms(1, 0, 0));
+
+  if *utc >= begin && *utc < end {
+    FixedOffset::east(SUMMER)
+  } else {
+    FixedOffset::east(WINTER)
+  }
+}
+
+pub fn to_local(utc :&DateTime<UTC>) -> DateTime<FixedOffset> {
+  utc.with_timezone(&offset_at(utc))
+}
+
+pub fn now() -> DateTime<FixedOffset> {
+  to_local(&UTC::now())
+}
+
+/// Interpret a wall clock time of the space.
+/// Ambiguous times resolve to the summer time, skipped times are moved forward by one hour.
+pub fn from_local(local :&NaiveDateTime) -> DateTime<UTC> {
+  let summer = UTC.from_utc_datetime(&(*local - Duration::seconds(SUMMER as i64)));
+  if offset_at(&summer) == FixedOffset::east(SUMMER) {
+    summer
+  } else {
+    UTC.from_utc_datetime(&(*local - Duration::seconds(WINTER as i64)))
+  }
+}
+
+fn last_sunday(year :i32, month :u32) -> NaiveDate {
+  let mut day = NaiveDate::from_ymd(year, month + 1, 1).pred();
+  while day.weekday() != Weekday::Sun {
+    day = day.pred();
+  }
+  day
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{to_local, from_local};
+  use chrono::{UTC, NaiveDate, TimeZone, Timelike};
+
+  #[test]
+  fn winter() {
+    let utc = UTC.ymd(2016, 1, 11).and_hms(19, 0, 0);
+    assert_eq!(20, to_local(&utc).hour());
+    assert_eq!(utc, from_local(&NaiveDate::from_ymd(2016, 1, 11).and_hms(20, 0, 0)));
+  }
+
+  #[test]
+  fn summer() {
+    let utc = UTC.ymd(2016, 7, 11).and_hms(18, 0, 0);
+    assert_eq!(20, to_local(&utc).hour());
+    assert_eq!(utc, from_local(&NaiveDate::from_ymd(2016, 7, 11).and_hms(20, 0, 0)));
+  }
+
+  #[test]
+  fn switch_to_winter() {
+    // 2016-10-30 03:00 CEST becomes 02:00 CET
+    assert_eq!(2, to_local(&UTC.ymd(2016, 10, 30).and_hms(0, 59, 59)).hour());
+    assert_eq!(2, to_local(&UTC.ymd(2016, 10, 30).and_hms(1, 0, 0)).hour());
+    assert_eq!(3, to_local(&UTC.ymd(2016, 10, 30).and_hms(2, 0, 0)).hour());
+  }
+
+  #[test]
+  fn skipped_hour() {
+    // 2016-03-27 02:30 does not exist
+    assert_eq!(UTC.ymd(2016, 3, 27).and_hms(1, 30, 0), from_local(&NaiveDate::from_ymd(2016, 3, 27).and_hms(2, 30, 0)));
+  }
+}
@@ -8,204 +8,628 @@ extern crate rustc_serialize;
 extern crate spaceapi;
 extern crate env_logger;
 #[macro_use] extern crate log;
+#[macro_use] extern crate lazy_static;
 extern crate chrono;
+extern crate image;
+extern crate plotters;
+extern crate toml;
+extern crate libc;
 
-use telegram_bot::{Api, ListeningMethod, Message, MessageType, ListeningAction};
+use telegram_bot::{Api, Message, MessageType, Update};
 
+pub mod config;
 pub mod user_input_compiler;
-use user_input_compiler::Input;
+use outbox::{Outbox, Outgoing};
+use subscriptions::Subscribed;
 
 pub mod spaceapi_client;
+pub mod http_client;
 
 pub mod grammar;
 
-use std::time::Duration;
+pub mod feed_reader;
+pub mod storage;
+pub mod subscriptions;
+pub mod news;
+pub mod local_time;
+pub mod calendar;
+pub mod events;
+pub mod bot_api;
+pub mod geo;
+pub mod history;
+pub mod chart;
+pub mod forecast;
+pub mod outbox;
+pub mod updates;
+pub mod webhook;
+pub mod commands;
+pub mod telegram;
+pub mod irc;
+pub mod matrix;
+pub mod repl;
+pub mod cli;
+pub mod signals;
+
+use std::cmp;
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the blog feed is checked for new articles
+const NEWS_POLL_INTERVAL_SECONDS: u64 = 15 * 60;
+/// How often the calendar is checked for events to remind of
+const EVENTS_POLL_INTERVAL_SECONDS: u64 = 5 * 60;
+/// How often the sensor values are recorded
+const SAMPLE_INTERVAL_SECONDS: u64 = 5 * 60;
+/// Recorded sensor values older than this are removed once a day
+const HISTORY_RETENTION_DAYS: i64 = 366;
+/// How long getUpdates waits for new updates before returning an empty list
+const LONG_POLL_TIMEOUT_SECONDS: i64 = 30;
+/// How long to wait before polling again if getUpdates only returned updates which are not answered yet
+const PENDING_POLL_INTERVAL_SECONDS: u64 = 1;
+/// Threads answering messages, a slow command only delays the chats of its worker
+const WORKER_THREADS: usize = 4;
+/// How long the workers may take to answer the messages they received before shutting down
+const SHUTDOWN_TIMEOUT_SECONDS: u64 = 20;
 
 fn main() {
     env_logger::init().unwrap();
 
-    let mut sac = spaceapi_client::SpaceApiClient::init();
-    let mut last_processed_message_id = 0;
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let config_path = match args.iter().position(|a| a == "--config") {
+        Some(i) if i + 1 < args.len() => Some(PathBuf::from(args.drain(i..i + 2).nth(1).unwrap())),
+        Some(_) => {
+            let _ = writeln!(io::stderr(), "--config needs a file name\n{}", cli::USAGE);
+            process::exit(2);
+        },
+        None => None,
+    };
+    match config::load(config_path.as_ref().map(|p| p.as_path())) {
+        Ok(config) => config::set(config),
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "Invalid configuration:\n{}", e);
+            process::exit(2);
+        },
+    }
 
-    let max_backoff_seconds = Duration::from_secs(128);
-    let min_backoff_seconds = Duration::from_secs(1);
-    let mut backoff_seconds = min_backoff_seconds;
-    
-    loop {
-        // Create bot, test simple API call and print bot information
-        let api = Api::from_env("TELEGRAM_BOT_TOKEN").unwrap();
-        info!("getMe: {:?}", api.get_me());
-        let mut listener = api.listener(ListeningMethod::LongPoll(None));
-        
-        
-        // Fetch new updates via long poll method
-        let res = listener.listen(|u| {
-            // Restore backoff_seconds, since it works again
-            backoff_seconds = min_backoff_seconds;
-            
-            // If the received update contains a message...
-            if let Some(m) = u.message {
-
-                // Discard messages from groups the bot is no longer part of
-                if m.message_id == last_processed_message_id {
-                    warn!("Dropped message: {:?}", m);
-                    return Ok(ListeningAction::Continue);
-                } else {
-                    last_processed_message_id = m.message_id;
+    if args.first().map_or(false, |a| a == "--repl") {
+        run_repl(args.get(1).map_or(".", |d| d));
+        return;
+    }
+    match cli::parse(&args) {
+        Ok(Some(invocation)) => {
+            if let Err(e) = cli::run(&local_commands(), invocation, &mut io::stdout()) {
+                let _ = writeln!(io::stderr(), "{}", e);
+                process::exit(1);
+            }
+            return;
+        },
+        Ok(None) => (),
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "{}\n{}", e, cli::USAGE);
+            process::exit(2);
+        },
+    }
+
+    let token = match config::get().token.clone() {
+        Some(token) => token,
+        None => {
+            let _ = writeln!(io::stderr(), "Set telegram.token in {} or TELEGRAM_BOT_TOKEN", config::DEFAULT_PATH);
+            process::exit(2);
+        },
+    };
+
+    let sac = Arc::new(Mutex::new(spaceapi_client::SpaceApiClient::init()));
+    // All replies and announcements go through the outbox, which keeps to the rate limits of Telegram
+    let outbox = Arc::new(Outbox::start(Api::from_token(&token).unwrap()));
+
+    signals::listen();
+
+    let news = Arc::new(Mutex::new(news::News::load()));
+    {
+        let (news, outbox) = (news.clone(), outbox.clone());
+        thread::spawn(move || announce_news(news, outbox));
+    }
+
+    let events = Arc::new(Mutex::new(events::Events::load()));
+    {
+        let (events, outbox) = (events.clone(), outbox.clone());
+        thread::spawn(move || remind_events(events, outbox));
+    }
+
+    thread::spawn(record_history);
+
+    let commands = Arc::new(commands::Commands::new(sac, news.clone(), events.clone(), history::History::open()));
+
+    if let Some(config) = config::get().irc.clone() {
+        let commands = commands.clone();
+        thread::spawn(move || irc::run(&config, &*commands));
+    }
+
+    if let Some(config) = config::get().matrix.clone() {
+        let commands = commands.clone();
+        thread::spawn(move || matrix::run(&config, &*commands));
+    }
+
+    // Every worker reports when it answered its last message
+    let updates = Arc::new(Mutex::new(updates::Updates::load()));
+    let (finished, workers_finished) = mpsc::channel();
+    let workers: Vec<mpsc::Sender<Job>> = (0..WORKER_THREADS).map(|_| {
+        let (tx, rx) = mpsc::channel();
+        let (commands, news, events, outbox, updates) = (commands.clone(), news.clone(), events.clone(), outbox.clone(), updates.clone());
+        let finished = finished.clone();
+        thread::spawn(move || {
+            handle_messages(rx, commands, news, events, outbox, updates);
+            let _ = finished.send(());
+        });
+        tx
+    }).collect();
+
+    let api = Api::from_token(&token).unwrap();
+
+    let bot_id = match get_bot_id(&api) {
+        Some(bot_id) => bot_id,
+        None => return,
+    };
+
+    let dispatcher = Arc::new(Dispatcher{
+        updates: updates,
+        bot_id: bot_id,
+        workers: Mutex::new(workers),
+        news: news,
+        events: events,
+    });
+
+    let listening = match config::get().webhook {
+        Some(ref config) => match receive_webhook(&api, config, dispatcher.clone()) {
+            Some(listening) => Some(listening),
+            None => return,
+        },
+        None => {
+            let dispatcher = dispatcher.clone();
+            thread::spawn(move || poll_updates(&api, &dispatcher));
+            None
+        },
+    };
+
+    // Blocks until SIGTERM or SIGINT
+    watch_signals(&outbox);
+
+    info!("Shutting down");
+    dispatcher.close();
+    if let Some(mut listening) = listening {
+        if let Err(e) = listening.close() {
+            warn!("Unable to stop the webhook server: {}", e);
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(SHUTDOWN_TIMEOUT_SECONDS);
+    for _ in 0..WORKER_THREADS {
+        let now = Instant::now();
+        if now >= deadline || workers_finished.recv_timeout(deadline - now).is_err() {
+            warn!("Not all messages were answered within {} seconds", SHUTDOWN_TIMEOUT_SECONDS);
+            break;
+        }
+    }
+
+    dispatcher.save_all();
+    info!("Stopped");
+}
+
+/// Answer commands from stdin, pictures and locations are written to `dir`
+fn run_repl(dir: &str) {
+    let stdin = io::stdin();
+    if let Err(e) = repl::run(&local_commands(), stdin.lock(), &mut io::stdout(), Path::new(dir)) {
+        error!("REPL: {}", e);
+    }
+}
+
+/// The commands for the REPL and the subcommands, without the background threads of the bot
+fn local_commands() -> commands::Commands<Mutex<spaceapi_client::SpaceApiClient>> {
+    let sac = Arc::new(Mutex::new(spaceapi_client::SpaceApiClient::init()));
+    let news = Arc::new(Mutex::new(news::News::load()));
+    let events = Arc::new(Mutex::new(events::Events::load()));
+    commands::Commands::new(sac, news, events, history::History::open())
+}
+
+/// A message for a worker, its update is confirmed to Telegram once it is answered
+struct Job {
+    update_id: i64,
+    message: Message,
+    /// Dropped once the message is answered
+    answered: Option<mpsc::Sender<()>>,
+}
+
+/// Hands the updates over to the workers, shared by long polling and the webhook
+struct Dispatcher {
+    updates: Arc<Mutex<updates::Updates>>,
+    bot_id: i64,
+    // Messages of a chat always go to the same worker, so its replies stay in order
+    workers: Mutex<Vec<mpsc::Sender<Job>>>,
+    news: Arc<Mutex<news::News>>,
+    events: Arc<Mutex<events::Events>>,
+}
+
+impl Dispatcher {
+    fn offset(&self) -> Option<i64> {
+        self.updates.lock().unwrap().offset()
+    }
+
+    /// True if the update was handed over before, it is skipped then
+    fn seen(&self, update_id: i64) -> bool {
+        self.updates.lock().unwrap().seen(update_id)
+    }
+
+    /// Returns false once the dispatcher is closed, the update is not confirmed then and Telegram delivers it again.
+    /// `answered` is dropped once the update is processed.
+    fn dispatch(&self, u: Update, answered: Option<mpsc::Sender<()>>) -> bool {
+        // Held until the message is handed over, closing waits for it
+        let workers = self.workers.lock().unwrap();
+        if workers.is_empty() {
+            return false;
+        }
+
+        if !self.updates.lock().unwrap().receive(u.update_id) {
+            debug!("Skipped update {}, it was received before", u.update_id);
+            return true;
+        }
+
+        // If the received update contains a message...
+        if let Some(m) = u.message {
+            let chat_id = m.chat.id();
+
+            // Nobody can read replies in a group the bot was removed from
+            let removed = match m.msg {
+                MessageType::LeftChatParticipant(ref user) => user.id == self.bot_id,
+                _ => false,
+            };
+            if removed {
+                info!("Removed from {}", chat_id);
+                forget_chat(chat_id, &self.news, &self.events);
+            } else {
+                // The worker confirms the update once it answered
+                let job = Job{ update_id: u.update_id, message: m, answered: answered };
+                match workers[(chat_id.abs() as usize) % workers.len()].send(job) {
+                    Ok(()) => return true,
+                    Err(e) => warn!("Unable to hand update {} over to a worker", e.0.update_id),
+                }
+            }
+        }
+
+        self.updates.lock().unwrap().answered(u.update_id);
+        true
+    }
+
+    /// Stop handing over updates, the workers end after answering the messages they have
+    fn close(&self) {
+        self.workers.lock().unwrap().clear();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.workers.lock().unwrap().is_empty()
+    }
+
+    /// A restart continues after the answered updates
+    fn save(&self) {
+        if let Err(e) = self.updates.lock().unwrap().save() {
+            warn!("Unable to save the update offset: {}", e);
+        }
+    }
+
+    /// Save the offset and the subscriptions before exiting
+    fn save_all(&self) {
+        self.save();
+        if let Err(e) = self.news.lock().unwrap().save() {
+            warn!("Unable to save news subscriptions: {}", e);
+        }
+        if let Err(e) = self.events.lock().unwrap().save() {
+            warn!("Unable to save event subscriptions: {}", e);
+        }
+    }
+}
+
+/// Fetch the updates with getUpdates, the default
+/// The id is needed to notice when the bot is removed from a group, so getMe is retried until it succeeds.
+/// Returns None if the bot is shut down meanwhile.
+fn get_bot_id(api: &Api) -> Option<i64> {
+    let mut backoff_seconds = Duration::from_secs(config::get().min_backoff_seconds);
+
+    while !signals::shutdown_requested() {
+        match api.get_me() {
+            Ok(me) => {
+                info!("getMe: {:?}", me);
+                return Some(me.id);
+            },
+            Err(e) => {
+                warn!("getMe failed: {}\nSleeping for {} seconds", e, backoff_seconds.as_secs());
+                if !signals::sleep(backoff_seconds) {
+                    break;
+                }
+
+                let max_backoff_seconds = Duration::from_secs(config::get().max_backoff_seconds);
+                if backoff_seconds < max_backoff_seconds {
+                    backoff_seconds = cmp::min(backoff_seconds * 2, max_backoff_seconds);
                 }
-                
-                let name = m.from.first_name;
-
-                // Match message type
-                match m.msg {
-                    MessageType::Text(t) => {
-                        // Print received text message to stdout
-                        info!("<{}> {}", name, t);
-                        let t = t.replace("@CoreDumpBot", "");
-                        let ts:String = format!("{}", t.trim() );
-                        
-                        match Input::from(ts) {
-                        Input::WebCam{ nth } => {
-                            let cams = sac.get_webcams();
-                            
-                            let no_filter = if let Some(nth) = nth {
-                                if nth >= cams.len() {
-                                    try!(send_message(&api, m.chat.id(),
-                                        format!("You requested the webcam #{}, but there are just {}", nth, cams.len())
-                                    ));
-                                    return Ok(ListeningAction::Continue);
-                                }
-                                
-                                false
-                            } else {
-                                true
-                            };
-                            
-                            let mut n : usize = 0;
-                            let w =
-                                cams.iter().filter(|_| {
-                                    let b = no_filter || Some(n) == nth;
-                                    n += 1;
-                                    b
-                                })
-                            ;
-                            
-                            for pic_path in w {
-                                let caption = sac.basename(&pic_path);
-                                match sac.get_tmp_path_for_webcam(&pic_path) {
-                                    Ok(pic_tmp_path) => {
-                                        try!(api.send_photo(
-                                                m.chat.id(),
-                                                pic_tmp_path, // Path
-                                                Some(caption.into()), // caption
-                                                None, // reply_to_message_id
-                                                None  // reply_markup
-                                        ));
-                                    },
-                                    Err(e) => {
-                                        println!("Webcam({:?}) Error: {:?}",nth, e);
-                                        // TODO send Error
-                                    },
-                                }
-                            }
-                        },
-                        Input::Help => {
-                            try!(send_message(&api, m.chat.id(),
-                                    "No such help 😜\n\
-                                    use /webcam for a snapshot of the 3D printer.\n\
-                                    use /status for an update on people now present\n\
-                                    use /grammar to receive the spec".into())
-                            );
-                        },
-                        Input::Status => {
-                            let s = match sac.fetch_aggregated_status() {
-                                Ok(people_now_present) => people_now_present,
-                                Err(e) => format!("An error occurred 😕\n{}", e),
-                            };
-                            try!(send_message(&api, m.chat.id(),s));
-                        },
-                        Input::Start => {
-                            try!(send_message(&api, m.chat.id(),
-                                    "Welcome to CoredumpBot\nuse /help to see available commands.".into())
-                            );
-                        },
-                        Input::Version => {
-                            try!(send_message(&api, m.chat.id(),
-                                    format!("Version: {}", env!("CARGO_PKG_VERSION")))
-                            );
-                        },
-                        Input::Grammar => {
-                            try!(
-                              api.send_message(
-                                m.chat.id(),     // chat_id                  : Integer
-                                grammar::get_grammar_string(),     // text                     : String
-                                Some(telegram_bot::types::ParseMode::Markdown),        // parse_mode               : Option<ParseMode>
-                                None,        // disable_web_page_preview : Option<bool>
-                                None,        // reply_to_message_id      : Option<Integer>
-                                None)        // reply_markup             : Option<ReplyMakrup>
-                            );
-                        },
-                        Input::Location => {
-                            let loc = sac.get_location();
-                            try!(api.send_location(
-                                    m.chat.id(),
-                                    loc.lat as f32, loc.lon as f32,
-                                    None, None
-                            ));
-                        },
-                        Input::InvalidSyntax( msg ) => {
-                            if m.chat.is_user() {
-                                try!(send_message(&api, m.chat.id(),
-                                        format!("Invalid syntax: {}\ntry /grammar", msg)
-                                ));
-                            }
-                        },
-                        _ => {
-                            if m.chat.is_user() {
-                                try!(
-                                    send_message(&api, m.chat.id(),
-                                        "Unknown command. Try /help".into())
-                                );
-                            }
-                        }, 
+            },
+        }
+    }
+
+    None
+}
+
+fn poll_updates(api: &Api, dispatcher: &Dispatcher) {
+    // getUpdates is refused while a webhook is registered
+    if let Err(e) = api.set_webhook(None::<&str>) {
+        warn!("Unable to remove the webhook: {}", e);
+    }
+
+    let mut backoff_seconds = Duration::from_secs(config::get().min_backoff_seconds);
+
+    while !dispatcher.is_closed() {
+        // Fetch new updates via long poll, the offset confirms the previous ones to Telegram
+        let res = api.get_updates(dispatcher.offset(), None, Some(LONG_POLL_TIMEOUT_SECONDS));
+        let batch = match res {
+            Ok(batch) => batch,
+            // Replies are sent by the workers, only failures of getUpdates end up here
+            Err(e) => {
+                warn!("An error occurred: {}\nSleeping for {} seconds", e, backoff_seconds.as_secs());
+
+                // Wait for a certain amount of seconds, a shutdown closes the dispatcher meanwhile
+                signals::sleep(backoff_seconds);
+
+                // Double the backoff time up to a max value
+                let max_backoff_seconds = Duration::from_secs(config::get().max_backoff_seconds);
+                if backoff_seconds < max_backoff_seconds {
+                    backoff_seconds = cmp::min(backoff_seconds * 2, max_backoff_seconds);
+                }
+                continue;
+            },
+        };
+
+        // Restore backoff_seconds, since it works again
+        backoff_seconds = Duration::from_secs(config::get().min_backoff_seconds);
+
+        // The offset stays at an update until it is answered, so Telegram returns it again right away
+        if batch.iter().all(|u| dispatcher.seen(u.update_id)) {
+            if !batch.is_empty() {
+                thread::sleep(Duration::from_secs(PENDING_POLL_INTERVAL_SECONDS));
+            }
+            continue;
+        }
+
+        // Updates after closing are not confirmed, Telegram delivers them again after the restart
+        for u in batch {
+            if !dispatcher.dispatch(u, None) {
+                break;
+            }
+        }
+        dispatcher.save();
+    }
+}
+
+/// Register the webhook and start the embedded server receiving the updates
+fn receive_webhook(api: &Api, config: &config::Webhook, dispatcher: Arc<Dispatcher>) -> Option<hyper::server::Listening> {
+    // A self-signed certificate has to be uploaded, the telegram-bot crate can only register the URL
+    let registered = match config.tls {
+        Some((ref cert, _)) => bot_api::set_webhook_with_certificate(&config.endpoint(), Path::new(cert)).map(|_| ()),
+        None => api.set_webhook(Some(&*config.endpoint())).map(|_| ()),
+    };
+    if let Err(e) = registered {
+        error!("Unable to register the webhook: {}", e);
+        return None;
+    }
+
+    let listening = webhook::serve(config, move |u| {
+        // Telegram delivers the update again unless it gets an answer, so the answer waits for the reply
+        let (answered, processed) = mpsc::channel();
+        let accepted = dispatcher.dispatch(u, Some(answered));
+        let _ = processed.recv();
+        dispatcher.save();
+        accepted
+    });
+    match listening {
+        Ok(listening) => {
+            info!("Receiving updates on {}", listening.socket);
+            Some(listening)
+        },
+        Err(e) => {
+            error!("Unable to listen on {}: {}", config.listen, e);
+            None
+        },
+    }
+}
+
+/// Answer the messages of the chats assigned to this worker
+fn handle_messages(rx: mpsc::Receiver<Job>, handler: Arc<commands::Handler + Send + Sync>,
+        news: Arc<Mutex<news::News>>, events: Arc<Mutex<events::Events>>, outbox: Arc<Outbox>,
+        updates: Arc<Mutex<updates::Updates>>) {
+    for job in rx {
+        let chat_id = job.message.chat.id();
+        match telegram::handle_message(&outbox, &*handler, job.message) {
+            Ok(()) => (),
+            Err(ref e) if chat_is_gone(e) => {
+                info!("Unable to answer message in {}, the chat is gone: {}", chat_id, e);
+                forget_chat(chat_id, &news, &events);
+            },
+            Err(e) => {
+                warn!("Unable to answer message in {}: {}", chat_id, e);
+                // Might fail for the same reason, e.g. when the message was too long
+                if let Err(e) = send_message(&outbox, chat_id, format!("Sorry, I was unable to answer 😕\n{}", e)) {
+                    warn!("Unable to report the error to {}: {}", chat_id, e);
+                }
+            },
+        }
+
+        // Failures are not retried, a message which can not be answered would block the offset otherwise
+        let mut updates = updates.lock().unwrap();
+        updates.answered(job.update_id);
+        if let Err(e) = updates.save() {
+            warn!("Unable to save the update offset: {}", e);
+        }
+        drop(job.answered);
+    }
+}
+
+/// Poll the blog feed and post new articles to the subscribed chats
+fn announce_news(news: Arc<Mutex<news::News>>, outbox: Arc<Outbox>) {
+    let mut sac = spaceapi_client::SpaceApiClient::new();
+
+    loop {
+        if !config::get().features.news {
+            thread::sleep(Duration::from_secs(NEWS_POLL_INTERVAL_SECONDS));
+            continue;
+        }
+
+        match sac.fetch_news() {
+            Ok(entries) => {
+                let (fresh, subscribers) = {
+                    let mut news = news.lock().unwrap();
+                    let fresh = news.take_unseen(&entries);
+                    if let Err(e) = news.save() {
+                        warn!("Unable to save announced news: {}", e);
+                    }
+                    (fresh, news.subscriptions().chats())
+                };
+
+                // The feed lists the newest article first
+                for entry in fresh.iter().rev() {
+                    for chat_id in &subscribers {
+                        match send_message(&outbox, *chat_id, commands::format_news_entry(entry)) {
+                            Ok(_) => (),
+                            Err(ref e) if chat_is_gone(e) => {
+                                info!("Unsubscribing {} from news, the chat is gone: {}", chat_id, e);
+                                subscriptions::set(&*news, *chat_id, false);
+                            },
+                            Err(e) => warn!("Unable to announce {:?} to {}: {}", entry, chat_id, e),
                         }
-                    },
-                    _ => {
-                        if m.chat.is_user() {
-                            try!(
-                                send_message(&api, m.chat.id(),
-                                    "Unknown command. Try /help".into())
-                            );
+                    }
+                }
+            },
+            Err(e) => warn!("Unable to fetch news: {}", e),
+        }
+
+        thread::sleep(Duration::from_secs(NEWS_POLL_INTERVAL_SECONDS));
+    }
+}
+
+/// Poll the calendar and remind the subscribed chats shortly before an event starts
+fn remind_events(events: Arc<Mutex<events::Events>>, outbox: Arc<Outbox>) {
+    let mut sac = spaceapi_client::SpaceApiClient::new();
+
+    loop {
+        if !config::get().features.events {
+            thread::sleep(Duration::from_secs(EVENTS_POLL_INTERVAL_SECONDS));
+            continue;
+        }
+
+        match sac.fetch_calendar() {
+            Ok(calendar) => {
+                let now = chrono::UTC::now();
+                let upcoming = calendar::occurrences(&calendar, &now,
+                    &(now + chrono::Duration::minutes(events::REMINDER_LEAD_MINUTES)));
+
+                let (due, subscribers) = {
+                    let mut events = events.lock().unwrap();
+                    let due = events.take_due(&upcoming, &now);
+                    if let Err(e) = events.save() {
+                        warn!("Unable to save sent reminders: {}", e);
+                    }
+                    (due, events.subscriptions().chats())
+                };
+
+                for occurrence in due {
+                    for chat_id in &subscribers {
+                        match send_message(&outbox, *chat_id, format!("Reminder: {}", occurrence.describe())) {
+                            Ok(_) => (),
+                            Err(ref e) if chat_is_gone(e) => {
+                                info!("Unsubscribing {} from reminders, the chat is gone: {}", chat_id, e);
+                                subscriptions::set(&*events, *chat_id, false);
+                            },
+                            Err(e) => warn!("Unable to remind {} of {:?}: {}", chat_id, occurrence, e),
                         }
                     }
                 }
+            },
+            Err(e) => warn!("Unable to fetch calendar: {}", e),
+        }
+
+        thread::sleep(Duration::from_secs(EVENTS_POLL_INTERVAL_SECONDS));
+    }
+}
+
+/// Reload the configuration on SIGHUP, the admins are told if it is invalid.
+/// Returns on SIGTERM or SIGINT.
+fn watch_signals(outbox: &Outbox) {
+    while !signals::shutdown_requested() {
+        thread::sleep(Duration::from_secs(1));
+        if !signals::reload_requested() {
+            continue;
+        }
+
+        if let Err(e) = config::reload() {
+            warn!("Invalid configuration, the previous one stays active:\n{}", e);
+            for admin in &config::get().admins {
+                let message = format!("Reloading the configuration failed, the previous one stays active:\n{}", e);
+                if let Err(e) = send_message(outbox, *admin, message) {
+                    warn!("Unable to tell admin {} about the invalid configuration: {}", admin, e);
+                }
             }
+        }
+    }
+}
 
-            // If none of the "try!" statements returned an error: It's Ok!
-            Ok(ListeningAction::Continue)
-        });
+/// Fetch the status periodically and record the sensor values
+fn record_history() {
+    let history = history::History::open();
+    let mut sac = spaceapi_client::SpaceApiClient::new();
+    let mut pruned: Option<Instant> = None;
 
-        if let Err(e) = res {
-            warn!("An error occurred: {}\nSleeping for {} seconds", e, backoff_seconds.as_secs());
+    loop {
+        if !config::get().features.history {
+            thread::sleep(Duration::from_secs(SAMPLE_INTERVAL_SECONDS));
+            continue;
+        }
 
-            // Wait for a certain amount of seconds
-            std::thread::sleep(backoff_seconds);
-            
-            // Double the backoff time up to a max value
-            if backoff_seconds < max_backoff_seconds {
-                backoff_seconds = backoff_seconds * 2;
+        match sac.fetch_sensor_samples() {
+            Ok(samples) => if let Err(e) = history.append(&samples) {
+                warn!("Unable to record history: {}", e);
+            },
+            Err(e) => warn!("Unable to fetch sensor values: {}", e),
+        }
+
+        if pruned.map_or(true, |pruned| pruned.elapsed() >= Duration::from_secs(24 * 60 * 60)) {
+            let before = chrono::UTC::now().timestamp() - HISTORY_RETENTION_DAYS * 24 * 60 * 60;
+            match history.prune(before) {
+                Ok(0) => {},
+                Ok(removed) => info!("Removed {} samples older than {} days from the history", removed, HISTORY_RETENTION_DAYS),
+                Err(e) => warn!("Unable to remove old samples from the history: {}", e),
             }
+            pruned = Some(Instant::now());
         }
+
+        thread::sleep(Duration::from_secs(SAMPLE_INTERVAL_SECONDS));
     }
 }
 
-fn send_message(api: &Api, chat_id: i64, message: String) -> Result<Message,telegram_bot::Error> {
-    api.send_message(
-        chat_id,     // chat_id                  : Integer
-        message,     // text                     : String
-        None,        // parse_mode               : Option<ParseMode>
-        None,        // disable_web_page_preview : Option<bool>
-        None,        // reply_to_message_id      : Option<Integer>
-        None)        // reply_markup             : Option<ReplyMakrup>
+/// True if Telegram refuses to deliver to the chat, e.g. the bot was blocked or removed from the group
+fn chat_is_gone(e: &telegram_bot::Error) -> bool {
+    match *e {
+        telegram_bot::Error::Api(ref description) =>
+            description.contains("Forbidden") || description.contains("chat not found"),
+        _ => false,
+    }
+}
+
+/// Drop the subscriptions of a chat the bot can no longer write to
+fn forget_chat(chat_id: i64, news: &Mutex<news::News>, events: &Mutex<events::Events>) {
+    subscriptions::set(news, chat_id, false);
+    subscriptions::set(events, chat_id, false);
 }
 
+fn send_message(outbox: &Outbox, chat_id: i64, message: String) -> Result<(), telegram_bot::Error> {
+    outbox.send(chat_id, Outgoing::Text{ text: message, parse_mode: None })
+}
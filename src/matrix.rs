@@ -0,0 +1,337 @@
+//! Matrix frontend using the client-server API, see https://matrix.org/docs/spec/client_server/r0.2.0.html
+//!
+//! Commands start with `!` because most clients intercept `/`.
+//! Messages sent while the bot was offline are not answered, the first sync only marks the position.
+
+use commands::{Context, Handler, Reply, local_chat_id};
+use config as settings;
+use hyper::Client;
+use hyper::header::{Authorization, Bearer, ContentType};
+use hyper::method::Method;
+use rustc_serialize::json::{Json, Object};
+use user_input_compiler::Input;
+
+use std::cmp;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// How long the homeserver holds a sync open when there are no new events
+const SYNC_TIMEOUT_MILLIS :u64 = 30 * 1000;
+const READ_TIMEOUT_SECONDS :u64 = 60;
+
+pub struct MatrixClient<'a> {
+  config :&'a settings::Matrix,
+  client :Client,
+  user_id :String,
+  next_batch :Option<String>,
+  transaction :u64,
+}
+
+/// Stay connected to the homeserver, reconnecting with a growing delay within the configured bounds
+pub fn run(config :&settings::Matrix, handler :&Handler) {
+  let mut backoff_seconds = settings::get().min_backoff_seconds;
+
+  loop {
+    match MatrixClient::connect(config) {
+      Ok(mut client) => loop {
+        match client.sync(handler) {
+          Ok(()) => backoff_seconds = settings::get().min_backoff_seconds,
+          Err(e) => {
+            warn!("Matrix: {}", e);
+            break;
+          },
+        }
+      },
+      Err(e) => warn!("Matrix: {}", e),
+    }
+
+    thread::sleep(Duration::from_secs(backoff_seconds));
+    backoff_seconds = cmp::min(backoff_seconds * 2, settings::get().max_backoff_seconds);
+  }
+}
+
+impl<'a> MatrixClient<'a> {
+  /// Find out who we are and join the rooms
+  pub fn connect(config :&'a settings::Matrix) -> Result<MatrixClient<'a>, String> {
+    let mut client = Client::new();
+    client.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECONDS)));
+
+    let mut matrix = MatrixClient{
+      config: config,
+      client: client,
+      user_id: String::new(),
+      next_batch: None,
+      transaction: 0,
+    };
+
+    let whoami = try!(matrix.call(Method::Get, "/_matrix/client/r0/account/whoami", None));
+    matrix.user_id = try!(whoami.find("user_id").and_then(|u| u.as_string()).ok_or("whoami: no user_id".to_string())).into();
+    info!("Matrix: logged in as {}", matrix.user_id);
+
+    for room in &config.rooms {
+      try!(matrix.call(Method::Post, &format!("/_matrix/client/r0/join/{}", encode_path_segment(room)), Some(Json::Object(Object::new()))));
+    }
+
+    Ok(matrix)
+  }
+
+  /// Wait for new events and answer the commands among them
+  pub fn sync(&mut self, handler :&Handler) -> Result<(), String> {
+    let path = match self.next_batch {
+      Some(ref since) => format!("/_matrix/client/r0/sync?timeout={}&since={}", SYNC_TIMEOUT_MILLIS, encode_path_segment(since)),
+      None => "/_matrix/client/r0/sync?timeout=0".into(),
+    };
+    let response = try!(self.call(Method::Get, &path, None));
+    let first_sync = self.next_batch.is_none();
+    self.next_batch = response.find("next_batch").and_then(|n| n.as_string()).map(String::from);
+
+    if first_sync {
+      return Ok(());
+    }
+
+    for (room_id, text) in messages(&response, &self.user_id) {
+      let text = text.trim();
+      if !text.starts_with('!') {
+        continue;
+      }
+      info!("Matrix <{}> {}", room_id, text);
+
+      let context = Context{ chat_id: local_chat_id("matrix", &room_id), private: false, pictures: true, user_id: None };
+      let replies = match Input::from(format!("/{}", &text[1..])) {
+        Input::NewsSubscription{ .. } | Input::EventsSubscription{ .. } =>
+          vec![Reply::Text("Subscriptions are only available on Telegram".into())],
+        input => handler.handle(&context, input),
+      };
+
+      for reply in replies {
+        if let Err(e) = self.send_reply(&room_id, reply) {
+          warn!("Matrix: unable to answer in {}: {}", room_id, e);
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn send_reply(&mut self, room_id :&str, reply :Reply) -> Result<(), String> {
+    let content = match reply {
+      Reply::Text(text) | Reply::Markdown(text) => object(vec![
+        ("msgtype", Json::String("m.text".into())),
+        ("body", Json::String(text)),
+      ]),
+      Reply::Photo{ path, caption } => {
+        let (url, info) = try!(self.upload(&path));
+        object(vec![
+          ("msgtype", Json::String("m.image".into())),
+          ("body", Json::String(caption)),
+          ("url", Json::String(url)),
+          ("info", info),
+        ])
+      },
+      Reply::Document(path) => {
+        let (url, info) = try!(self.upload(&path));
+        object(vec![
+          ("msgtype", Json::String("m.file".into())),
+          ("body", Json::String(file_name(&path))),
+          ("url", Json::String(url)),
+          ("info", info),
+        ])
+      },
+      Reply::Venue{ latitude, longitude, text, .. } => object(vec![
+        ("msgtype", Json::String("m.location".into())),
+        ("body", Json::String(text)),
+        ("geo_uri", Json::String(format!("geo:{},{}", latitude, longitude))),
+      ]),
+    };
+
+    self.transaction += 1;
+    let path = format!("/_matrix/client/r0/rooms/{}/send/m.room.message/{}-{}",
+      encode_path_segment(room_id), ::chrono::UTC::now().timestamp(), self.transaction);
+    self.call(Method::Put, &path, Some(content)).map(|_| ())
+  }
+
+  /// Upload the file to the media repository, returns its `mxc://` URL and the info for the message
+  fn upload(&self, path :&Path) -> Result<(String, Json), String> {
+    let mut body = vec![];
+    try!(File::open(path).and_then(|mut f| f.read_to_end(&mut body)).map_err(|e| format!("{:?}: {}", path, e)));
+    let mimetype = mimetype(path);
+
+    let url = format!("{}/_matrix/media/r0/upload?filename={}", self.config.homeserver, encode_path_segment(&file_name(path)));
+    let mut res = try!(self.client.post(&*url)
+      .header(Authorization(Bearer{ token: self.config.access_token.clone() }))
+      .header(ContentType(mimetype.parse().unwrap()))
+      .body(&body[..])
+      .send()
+      .map_err(|e| format!("upload: {}", e)));
+    let response = try!(read_json("upload", &mut res));
+
+    let content_uri = try!(response.find("content_uri").and_then(|u| u.as_string()).ok_or("upload: no content_uri".to_string()));
+    let info = object(vec![
+      ("mimetype", Json::String(mimetype.into())),
+      ("size", Json::U64(body.len() as u64)),
+    ]);
+    Ok((content_uri.into(), info))
+  }
+
+  fn call(&self, method :Method, path :&str, body :Option<Json>) -> Result<Json, String> {
+    let url = format!("{}{}", self.config.homeserver, path);
+    let body = body.map(|b| b.to_string());
+
+    let mut request = self.client.request(method, &*url)
+      .header(Authorization(Bearer{ token: self.config.access_token.clone() }));
+    if let Some(ref body) = body {
+      request = request.header(ContentType::json()).body(&**body);
+    }
+    let mut res = try!(request.send().map_err(|e| format!("{}: {}", path, e)));
+
+    read_json(path, &mut res)
+  }
+}
+
+fn read_json(what :&str, res :&mut ::hyper::client::Response) -> Result<Json, String> {
+  let mut body = String::new();
+  try!(res.read_to_string(&mut body).map_err(|e| format!("{}: {}", what, e)));
+  let json = try!(Json::from_str(&body).map_err(|e| format!("{}: unable to parse response: {}", what, e)));
+
+  if !res.status.is_success() {
+    let error = json.find("error").and_then(|e| e.as_string()).unwrap_or("no description");
+    return Err(format!("{}: {} {}", what, res.status, error));
+  }
+  Ok(json)
+}
+
+/// Room id and body of the text messages of others in the joined rooms
+fn messages(sync :&Json, user_id :&str) -> Vec<(String, String)> {
+  let rooms = match sync.find_path(&["rooms", "join"]).and_then(|j| j.as_object()) {
+    Some(rooms) => rooms,
+    None => return vec![],
+  };
+
+  let mut messages = vec![];
+  for (room_id, room) in rooms {
+    let events = match room.find_path(&["timeline", "events"]).and_then(|e| e.as_array()) {
+      Some(events) => events,
+      None => continue,
+    };
+    for event in events {
+      let string = |path :&[&str]| event.find_path(path).and_then(|s| s.as_string());
+      if string(&["type"]) == Some("m.room.message") && string(&["content", "msgtype"]) == Some("m.text")
+          && string(&["sender"]) != Some(user_id) {
+        if let Some(body) = string(&["content", "body"]) {
+          messages.push((room_id.clone(), body.to_string()));
+        }
+      }
+    }
+  }
+  messages
+}
+
+fn object(pairs :Vec<(&str, Json)>) -> Json {
+  Json::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn file_name(path :&Path) -> String {
+  path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn mimetype(path :&Path) -> &'static str {
+  match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+    Some(ref e) if e == "jpg" || e == "jpeg" => "image/jpeg",
+    Some(ref e) if e == "png" => "image/png",
+    Some(ref e) if e == "csv" => "text/csv",
+    Some(ref e) if e == "json" => "application/json",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Percent-encode everything except the unreserved characters of RFC 3986
+fn encode_path_segment(s :&str) -> String {
+  s.bytes().map(|b| match b {
+    b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+    _ => format!("%{:02X}", b),
+  }).collect()
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{MatrixClient, encode_path_segment};
+  use config::Matrix;
+  use commands::fixture::FixtureHandler;
+  use hyper::server::{Server, Request, Response};
+  use hyper::uri::RequestUri;
+  use rustc_serialize::json::Json;
+  use std::io::prelude::*;
+  use std::sync::{Arc, Mutex};
+
+  #[test]
+  fn path_segment() {
+    assert_eq!("%23coredump%3Amatrix.org", encode_path_segment("#coredump:matrix.org"));
+  }
+
+  /// A homeserver stand-in, returns the requests it received as "METHOD path body"
+  #[test]
+  fn conversation() {
+    let received = Arc::new(Mutex::new(vec![]));
+    let log = received.clone();
+    let mut listening = Server::http("127.0.0.1:0").unwrap().handle(move |mut req :Request, res :Response| {
+      let path = match req.uri { RequestUri::AbsolutePath(ref p) => p.clone(), _ => String::new() };
+      let mut body = vec![];
+      req.read_to_end(&mut body).unwrap();
+      log.lock().unwrap().push(format!("{} {} {}", req.method, path, String::from_utf8_lossy(&body)));
+
+      let answer = if path.starts_with("/_matrix/client/r0/account/whoami") {
+        r#"{"user_id":"@bot:example.org"}"#
+      } else if path.starts_with("/_matrix/client/r0/join/") {
+        r#"{"room_id":"!room:example.org"}"#
+      } else if path == "/_matrix/client/r0/sync?timeout=0" {
+        r#"{"next_batch":"s1","rooms":{"join":{"!room:example.org":{"timeline":{"events":[
+          {"type":"m.room.message","sender":"@alice:example.org","content":{"msgtype":"m.text","body":"!status"}}]}}}}}"#
+      } else if path.starts_with("/_matrix/client/r0/sync?") {
+        r#"{"next_batch":"s2","rooms":{"join":{"!room:example.org":{"timeline":{"events":[
+          {"type":"m.room.message","sender":"@alice:example.org","content":{"msgtype":"m.text","body":"hello"}},
+          {"type":"m.room.message","sender":"@bot:example.org","content":{"msgtype":"m.text","body":"!status"}},
+          {"type":"m.room.member","sender":"@alice:example.org","content":{"membership":"join"}},
+          {"type":"m.room.message","sender":"@alice:example.org","content":{"msgtype":"m.text","body":"!webcam"}},
+          {"type":"m.room.message","sender":"@alice:example.org","content":{"msgtype":"m.text","body":"!location"}}]}}}}}"#
+      } else if path.starts_with("/_matrix/media/r0/upload") {
+        r#"{"content_uri":"mxc://example.org/abc"}"#
+      } else {
+        r#"{"event_id":"$1"}"#
+      };
+      res.send(answer.as_bytes()).unwrap();
+    }).unwrap();
+
+    let config = Matrix{
+      homeserver: format!("http://{}", listening.socket),
+      access_token: "token".into(),
+      rooms: vec!["#coredump:example.org".into()],
+    };
+    {
+      let mut client = MatrixClient::connect(&config).unwrap();
+      client.sync(&FixtureHandler).unwrap();
+      client.sync(&FixtureHandler).unwrap();
+    }
+    listening.close().unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(7, received.len());
+    assert_eq!("POST /_matrix/client/r0/join/%23coredump%3Aexample.org {}", received[1]);
+    assert_eq!("GET /_matrix/client/r0/sync?timeout=30000&since=s1 ", received[3]);
+    assert_eq!("POST /_matrix/media/r0/upload?filename=coredump_bot_fixture_webcam.jpg jpeg", received[4]);
+
+    let image = received[5].splitn(3, ' ').collect::<Vec<_>>();
+    assert_eq!("PUT", image[0]);
+    assert!(image[1].starts_with("/_matrix/client/r0/rooms/%21room%3Aexample.org/send/m.room.message/"));
+    assert_eq!(Json::from_str(r#"{"msgtype":"m.image","body":"ultimaker.jpg","url":"mxc://example.org/abc","info":{"mimetype":"image/jpeg","size":4}}"#).unwrap(),
+      Json::from_str(image[2]).unwrap());
+
+    let location = received[6].splitn(3, ' ').collect::<Vec<_>>();
+    assert_eq!(Json::from_str(r#"{"msgtype":"m.location","body":"coredump\nRapperswil","geo_uri":"geo:47.5,8.75"}"#).unwrap(),
+      Json::from_str(location[2]).unwrap());
+  }
+}
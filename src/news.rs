@@ -0,0 +1,87 @@
+//! Announce new blog articles to subscribed chats
+
+use feed_reader::FeedEntry;
+use storage;
+use subscriptions::{Subscribed, Subscriptions};
+use std::io;
+
+/// Number of announced entry ids to remember
+const MAX_SEEN :usize = 200;
+
+#[derive(RustcEncodable, RustcDecodable, Default)]
+struct NewsState {
+  /// False until the feed was read once, prevents announcing the whole archive
+  initialized :bool,
+  subscribers :Subscriptions,
+  seen :Vec<String>,
+}
+
+/// `default()` starts without subscriptions and is not stored until saved
+#[derive(Default)]
+pub struct News {
+  state :NewsState,
+}
+
+impl News {
+  /// Restore the subscriptions and announced entries from the data directory
+  pub fn load() -> News {
+    News{
+      state: storage::load("news").unwrap_or(NewsState::default()),
+    }
+  }
+
+  /// Returns the entries which were not announced yet and remembers them as announced.
+  /// On the very first run everything is marked as seen and nothing is returned.
+  pub fn take_unseen(&mut self, entries :&[FeedEntry]) -> Vec<FeedEntry> {
+    let first_run = !self.state.initialized;
+    self.state.initialized = true;
+
+    let mut unseen = vec![];
+    for entry in entries {
+      if !self.state.seen.contains(&entry.id) {
+        self.state.seen.push(entry.id.clone());
+        if !first_run {
+          unseen.push(entry.clone());
+        }
+      }
+    }
+
+    if self.state.seen.len() > MAX_SEEN {
+      let overflow = self.state.seen.len() - MAX_SEEN;
+      self.state.seen.drain(..overflow);
+    }
+
+    unseen
+  }
+}
+
+impl Subscribed for News {
+  fn subscriptions(&mut self) -> &mut Subscriptions {
+    &mut self.state.subscribers
+  }
+
+  fn save(&self) -> Result<(), io::Error> {
+    storage::save("news", &self.state)
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::News;
+  use feed_reader::FeedEntry;
+
+  fn entry(id :&str) -> FeedEntry {
+    FeedEntry{ id: id.into(), title: id.into(), link: id.into() }
+  }
+
+  #[test]
+  fn first_run_announces_nothing() {
+    let mut news = News::default();
+
+    assert_eq!(Vec::<FeedEntry>::new(), news.take_unseen(&[entry("a"), entry("b")]));
+    assert_eq!(vec![entry("c")], news.take_unseen(&[entry("c"), entry("a"), entry("b")]));
+    assert_eq!(Vec::<FeedEntry>::new(), news.take_unseen(&[entry("c"), entry("a")]));
+  }
+}
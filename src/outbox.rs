@@ -0,0 +1,327 @@
+//! Queue for outgoing messages which keeps to the flood limits of Telegram
+//!
+//! A chat gets at most one message per second, a group one every three seconds
+//! and all chats together about 30 per second. If Telegram asks to "retry after" some seconds
+//! the whole queue waits that long. While the API is unreachable the messages stay queued,
+//! until they are given up after `SEND_TIMEOUT_SECONDS`.
+
+use bot_api;
+use telegram_bot::{Api, Error};
+use telegram_bot::types::ParseMode;
+
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const GLOBAL_INTERVAL_MILLIS :u64 = 35;
+const CHAT_INTERVAL_MILLIS :u64 = 1000;
+const GROUP_INTERVAL_MILLIS :u64 = 3000;
+const MAX_OUTAGE_BACKOFF_SECONDS :u64 = 64;
+/// How long a message may stay queued before the sender gets an error
+const SEND_TIMEOUT_SECONDS :u64 = 300;
+
+pub enum Outgoing {
+  Text{ text :String, parse_mode :Option<ParseMode> },
+  Photo{ path :PathBuf, caption :Option<String> },
+  Document{ path :PathBuf },
+  /// `text` is sent instead if Telegram refuses the venue
+  Venue{ latitude :f64, longitude :f64, title :String, address :String, text :String },
+}
+
+struct Job {
+  chat_id :i64,
+  outgoing :Outgoing,
+  deadline :Instant,
+  reply :Sender<Result<(), Error>>,
+}
+
+pub struct Outbox {
+  queue :Mutex<Sender<Job>>,
+}
+
+impl Outbox {
+  /// Start the thread sending the queued messages
+  pub fn start(api :Api) -> Outbox {
+    Outbox::with_sender(move |chat_id, outgoing| send(&api, chat_id, outgoing))
+  }
+
+  fn with_sender<F>(send :F) -> Outbox where F: FnMut(i64, &Outgoing) -> Result<(), Error> + Send + 'static {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || run(send, rx));
+
+    Outbox{ queue: Mutex::new(tx) }
+  }
+
+  /// Queue the message and wait until it is sent or given up
+  pub fn send(&self, chat_id :i64, outgoing :Outgoing) -> Result<(), Error> {
+    let (tx, rx) = mpsc::channel();
+    let timeout = Duration::from_secs(SEND_TIMEOUT_SECONDS);
+    let job = Job{ chat_id: chat_id, outgoing: outgoing, deadline: Instant::now() + timeout, reply: tx };
+
+    if self.queue.lock().unwrap().send(job).is_err() {
+      return Err(Error::InvalidState("the outbox is not running".into()));
+    }
+    // The queue answers at the deadline, waiting a little longer only guards against it being stuck
+    match rx.recv_timeout(timeout + Duration::from_secs(1)) {
+      Ok(result) => result,
+      Err(RecvTimeoutError::Timeout) => Err(timed_out()),
+      Err(RecvTimeoutError::Disconnected) => Err(Error::InvalidState("the outbox stopped".into())),
+    }
+  }
+}
+
+fn timed_out() -> Error {
+  Error::InvalidState(format!("the message could not be sent within {} seconds", SEND_TIMEOUT_SECONDS))
+}
+
+fn run<F>(mut send :F, rx :Receiver<Job>) where F: FnMut(i64, &Outgoing) -> Result<(), Error> {
+  let mut queue :VecDeque<Job> = VecDeque::new();
+  let mut pacing = Pacing::new();
+  let mut outage_backoff = 1;
+
+  loop {
+    let received = if queue.is_empty() {
+      rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+    } else {
+      rx.recv_timeout(Duration::from_millis(GLOBAL_INTERVAL_MILLIS))
+    };
+    match received {
+      Ok(job) => queue.push_back(job),
+      Err(RecvTimeoutError::Disconnected) if queue.is_empty() => return,
+      Err(RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(GLOBAL_INTERVAL_MILLIS)),
+      Err(RecvTimeoutError::Timeout) => (),
+    }
+    while let Ok(job) = rx.try_recv() {
+      queue.push_back(job);
+    }
+    give_up_expired(&mut queue, Instant::now());
+
+    let index = match next_ready(&queue, &pacing, Instant::now()) {
+      Some(index) => index,
+      None => continue,
+    };
+
+    let result = send(queue[index].chat_id, &queue[index].outgoing);
+    let now = Instant::now();
+    match result {
+      Err(Error::Api(ref description)) if retry_after(description).is_some() => {
+        let seconds = retry_after(description).unwrap();
+        warn!("Telegram asks to wait {} seconds: {}", seconds, description);
+        pacing.pause(now, Duration::from_secs(seconds));
+      },
+      Err(Error::Http(ref e)) => {
+        warn!("Telegram is unreachable, {} messages queued, retrying in {} seconds: {}", queue.len(), outage_backoff, e);
+        pacing.pause(now, Duration::from_secs(outage_backoff));
+        outage_backoff = cmp::min(outage_backoff * 2, MAX_OUTAGE_BACKOFF_SECONDS);
+      },
+      // A file which can not be read only concerns this message, nothing was sent
+      Err(e @ Error::Io(_)) => {
+        let job = queue.remove(index).unwrap();
+        let _ = job.reply.send(Err(e));
+      },
+      result => {
+        outage_backoff = 1;
+        let job = queue.remove(index).unwrap();
+        pacing.sent(job.chat_id, now);
+        // The sender may have given up waiting, that is fine
+        let _ = job.reply.send(result);
+      },
+    }
+  }
+}
+
+fn send(api :&Api, chat_id :i64, outgoing :&Outgoing) -> Result<(), Error> {
+  let sent = match *outgoing {
+    Outgoing::Text{ ref text, ref parse_mode } =>
+      api.send_message(chat_id, text.clone(), parse_mode.clone(), None, None, None),
+    Outgoing::Photo{ ref path, ref caption } =>
+      api.send_photo(chat_id, path, caption.clone(), None, None),
+    Outgoing::Document{ ref path } =>
+      api.send_document(chat_id, path, None, None),
+    Outgoing::Venue{ latitude, longitude, ref title, ref address, ref text } =>
+      return match bot_api::send_venue(chat_id, latitude, longitude, title, address) {
+        Err(Error::Api(ref description)) if retry_after(description).is_none() => {
+          warn!("Unable to send venue, falling back to text: {}", description);
+          api.send_message(chat_id, text.clone(), None, None, None, None).map(|_| ())
+        },
+        sent => sent.map(|_| ()),
+      },
+  };
+  sent.map(|_| ())
+}
+
+/// Answer the jobs whose senders stopped waiting with an error
+fn give_up_expired(queue :&mut VecDeque<Job>, now :Instant) {
+  let mut index = 0;
+  while index < queue.len() {
+    if now < queue[index].deadline {
+      index += 1;
+      continue;
+    }
+    let job = queue.remove(index).unwrap();
+    warn!("Giving up a message to {} after {} seconds", job.chat_id, SEND_TIMEOUT_SECONDS);
+    let _ = job.reply.send(Err(timed_out()));
+  }
+}
+
+/// The first job which may be sent now, jobs of a chat are sent in order
+fn next_ready(queue :&VecDeque<Job>, pacing :&Pacing, now :Instant) -> Option<usize> {
+  let mut waiting = HashSet::new();
+
+  for (index, job) in queue.iter().enumerate() {
+    if waiting.contains(&job.chat_id) {
+      continue;
+    }
+    if pacing.ready(job.chat_id, now) {
+      return Some(index);
+    }
+    waiting.insert(job.chat_id);
+  }
+
+  None
+}
+
+/// Seconds from a description like "Too Many Requests: retry after 35"
+fn retry_after(description :&str) -> Option<u64> {
+  let position = match description.to_lowercase().find("retry after ") {
+    Some(position) => position + "retry after ".len(),
+    None => return None,
+  };
+
+  description[position..].chars().take_while(|c| c.is_digit(10)).collect::<String>().parse().ok()
+}
+
+/// Remembers when messages were sent to decide when the next one may be sent
+struct Pacing {
+  last_sent :Option<Instant>,
+  last_sent_to :HashMap<i64, Instant>,
+  paused_until :Option<Instant>,
+}
+
+impl Pacing {
+  fn new() -> Pacing {
+    Pacing{
+      last_sent: None,
+      last_sent_to: HashMap::new(),
+      paused_until: None,
+    }
+  }
+
+  fn ready(&self, chat_id :i64, now :Instant) -> bool {
+    if let Some(paused_until) = self.paused_until {
+      if now < paused_until {
+        return false;
+      }
+    }
+    if let Some(last_sent) = self.last_sent {
+      if now < last_sent + Duration::from_millis(GLOBAL_INTERVAL_MILLIS) {
+        return false;
+      }
+    }
+    match self.last_sent_to.get(&chat_id) {
+      Some(&last_sent) => now >= last_sent + chat_interval(chat_id),
+      None => true,
+    }
+  }
+
+  fn sent(&mut self, chat_id :i64, now :Instant) {
+    self.last_sent = Some(now);
+    self.last_sent_to.insert(chat_id, now);
+
+    // Chats which could receive a message anyway do not need to be remembered
+    let interval = Duration::from_millis(GROUP_INTERVAL_MILLIS);
+    self.last_sent_to.retain(|_, last_sent| now < *last_sent + interval);
+  }
+
+  fn pause(&mut self, now :Instant, duration :Duration) {
+    self.paused_until = Some(now + duration);
+  }
+}
+
+/// Groups and channels have negative ids
+fn chat_interval(chat_id :i64) -> Duration {
+  if chat_id < 0 {
+    Duration::from_millis(GROUP_INTERVAL_MILLIS)
+  } else {
+    Duration::from_millis(CHAT_INTERVAL_MILLIS)
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{Outbox, Outgoing, Pacing, retry_after};
+  use telegram_bot::Error;
+  use std::fs::File;
+  use std::path::PathBuf;
+  use std::sync::{Arc, Mutex};
+  use std::time::{Duration, Instant};
+
+  #[test]
+  fn retry_after_description() {
+    assert_eq!(Some(35), retry_after("Too Many Requests: retry after 35"));
+    assert_eq!(Some(7), retry_after("[Error]: Too Many Requests: Retry after 7 seconds"));
+    assert_eq!(None, retry_after("Forbidden: bot was blocked by the user"));
+  }
+
+  #[test]
+  fn pacing_per_chat() {
+    let start = Instant::now();
+    let mut pacing = Pacing::new();
+    assert!(pacing.ready(1, start));
+
+    pacing.sent(1, start);
+    assert!(!pacing.ready(2, start + Duration::from_millis(10)));
+    assert!(pacing.ready(2, start + Duration::from_millis(50)));
+    assert!(!pacing.ready(1, start + Duration::from_millis(500)));
+    assert!(pacing.ready(1, start + Duration::from_millis(1000)));
+  }
+
+  #[test]
+  fn pacing_groups() {
+    let start = Instant::now();
+    let mut pacing = Pacing::new();
+
+    pacing.sent(-1, start);
+    assert!(!pacing.ready(-1, start + Duration::from_millis(2000)));
+    assert!(pacing.ready(-1, start + Duration::from_millis(3000)));
+  }
+
+  #[test]
+  fn pause() {
+    let start = Instant::now();
+    let mut pacing = Pacing::new();
+
+    pacing.pause(start, Duration::from_secs(5));
+    assert!(!pacing.ready(1, start + Duration::from_secs(4)));
+    assert!(pacing.ready(1, start + Duration::from_secs(5)));
+  }
+
+  #[test]
+  fn missing_file() {
+    let sent = Arc::new(Mutex::new(vec![]));
+    let outbox = {
+      let sent = sent.clone();
+      Outbox::with_sender(move |chat_id, outgoing| {
+        match *outgoing {
+          Outgoing::Photo{ ref path, .. } | Outgoing::Document{ ref path } => { try!(File::open(path).map_err(Error::Io)); },
+          Outgoing::Text{ ref text, .. } | Outgoing::Venue{ ref text, .. } => sent.lock().unwrap().push((chat_id, text.clone())),
+        }
+        Ok(())
+      })
+    };
+
+    let photo = Outgoing::Photo{ path: PathBuf::from("/nonexistent/coredump_bot_outbox_test.jpg"), caption: None };
+    assert!(match outbox.send(1, photo) { Err(Error::Io(_)) => true, _ => false });
+
+    let started = Instant::now();
+    assert!(outbox.send(1, Outgoing::Text{ text: "hello".into(), parse_mode: None }).is_ok());
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(vec![(1, "hello".to_string())], *sent.lock().unwrap());
+  }
+}
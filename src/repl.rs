@@ -0,0 +1,147 @@
+//! Try the commands on the terminal, without Telegram
+//!
+//! Every line is handled like a message in a private chat. Text replies are printed,
+//! pictures and documents are copied to the output directory and locations are written there as GeoJSON.
+//! A line like `@47.22,8.82` shares a location.
+
+use commands::{Context, Handler, Reply, local_chat_id};
+use rustc_serialize::json::{Json, Object};
+use user_input_compiler::Input;
+
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+
+const LOCATION_FILE :&'static str = "location.geojson";
+
+/// Read commands until the input ends
+pub fn run<R: BufRead, W: Write>(handler :&Handler, input :R, output :&mut W, dir :&Path) -> io::Result<()> {
+  let context = Context{ chat_id: local_chat_id("repl", ""), private: true, pictures: true, user_id: None };
+
+  try!(write!(output, "> "));
+  try!(output.flush());
+  for line in input.lines() {
+    let line = try!(line);
+    let line = line.trim();
+
+    let replies = if line.is_empty() {
+      vec![]
+    } else if line.starts_with('@') {
+      match parse_location(&line[1..]) {
+        Some((latitude, longitude)) => handler.handle_location(&context, latitude, longitude),
+        None => vec![Reply::Text("Share a location as @latitude,longitude".into())],
+      }
+    } else {
+      match Input::from(line.to_string()) {
+        // The announcements are only sent to Telegram chats
+        Input::NewsSubscription{ .. } | Input::EventsSubscription{ .. } =>
+          vec![Reply::Text("Subscriptions are not available in the REPL".into())],
+        input => handler.handle(&context, input),
+      }
+    };
+
+    for reply in replies {
+      try!(write_reply(output, dir, reply));
+    }
+    try!(write!(output, "> "));
+    try!(output.flush());
+  }
+
+  writeln!(output, "")
+}
+
+fn write_reply<W: Write>(output :&mut W, dir :&Path, reply :Reply) -> io::Result<()> {
+  match reply {
+    Reply::Text(text) | Reply::Markdown(text) => writeln!(output, "{}", text),
+    Reply::Photo{ path, caption } => {
+      let target = try!(copy_to(&path, dir));
+      writeln!(output, "[photo {}] {}", target, caption)
+    },
+    Reply::Document(path) => {
+      let target = try!(copy_to(&path, dir));
+      writeln!(output, "[document {}]", target)
+    },
+    Reply::Venue{ latitude, longitude, title, address, text } => {
+      let target = dir.join(LOCATION_FILE);
+      try!(File::create(&target).and_then(|mut f| f.write_all(geojson(latitude, longitude, title, address).as_bytes())));
+      writeln!(output, "[location {}] {}", target.display(), text)
+    },
+  }
+}
+
+/// Copy the file into the directory, keeping its name
+fn copy_to(path :&Path, dir :&Path) -> io::Result<String> {
+  let target = dir.join(path.file_name().unwrap_or(path.as_os_str()));
+  try!(fs::copy(path, &target));
+  Ok(target.display().to_string())
+}
+
+fn geojson(latitude :f64, longitude :f64, title :String, address :String) -> String {
+  let mut geometry = Object::new();
+  geometry.insert("type".into(), Json::String("Point".into()));
+  geometry.insert("coordinates".into(), Json::Array(vec![Json::F64(longitude), Json::F64(latitude)]));
+
+  let mut properties = Object::new();
+  properties.insert("title".into(), Json::String(title));
+  properties.insert("address".into(), Json::String(address));
+
+  let mut feature = Object::new();
+  feature.insert("type".into(), Json::String("Feature".into()));
+  feature.insert("geometry".into(), Json::Object(geometry));
+  feature.insert("properties".into(), Json::Object(properties));
+  Json::Object(feature).to_string()
+}
+
+/// `latitude,longitude` in degrees
+fn parse_location(s :&str) -> Option<(f64, f64)> {
+  let mut parts = s.split(',').map(|p| p.trim().parse::<f64>());
+  match (parts.next(), parts.next(), parts.next()) {
+    (Some(Ok(latitude)), Some(Ok(longitude)), None) => Some((latitude, longitude)),
+    _ => None,
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{parse_location, run};
+  use commands::fixture::FixtureHandler;
+  use rustc_serialize::json::Json;
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::prelude::*;
+
+  #[test]
+  fn location() {
+    assert_eq!(Some((47.22, 8.82)), parse_location("47.22, 8.82"));
+    assert_eq!(None, parse_location("47.22"));
+    assert_eq!(None, parse_location("47.22,8.82,1"));
+    assert_eq!(None, parse_location("north,east"));
+  }
+
+  #[test]
+  fn session() {
+    let dir = env::temp_dir().join("coredump_bot_repl_test");
+    let _ = fs::create_dir(&dir);
+    let input = "/status\n\nhello\n@47.2,8.8\n/news on\n/webcam 0\n/location\n";
+    let mut output = vec![];
+    run(&FixtureHandler, input.as_bytes(), &mut output, &dir).unwrap();
+
+    let expected = format!("> Status\n> > InvalidSyntax(\"Command must start with /\")\n> shared 47.2 8.8\n\
+      > Subscriptions are not available in the REPL\n> [photo {}] ultimaker.jpg\n> [location {}] coredump\nRapperswil\n> \n",
+      dir.join("coredump_bot_fixture_webcam.jpg").display(), dir.join("location.geojson").display());
+    assert_eq!(expected, String::from_utf8(output).unwrap());
+
+    let mut picture = String::new();
+    File::open(dir.join("coredump_bot_fixture_webcam.jpg")).unwrap().read_to_string(&mut picture).unwrap();
+    assert_eq!("jpeg", picture);
+
+    let mut location = String::new();
+    File::open(dir.join("location.geojson")).unwrap().read_to_string(&mut location).unwrap();
+    let location = Json::from_str(&location).unwrap();
+    assert_eq!(Some(&Json::Array(vec![Json::F64(8.75), Json::F64(47.5)])), location.find_path(&["geometry", "coordinates"]));
+    assert_eq!(Some("coredump"), location.find_path(&["properties", "title"]).and_then(|t| t.as_string()));
+  }
+}
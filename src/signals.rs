@@ -0,0 +1,107 @@
+//! Unix signals
+//!
+//! The handlers only set a flag, the work is done by a thread checking it.
+
+use libc;
+
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::thread;
+use std::time::Duration;
+
+/// How often sleep checks for a shutdown
+const SLEEP_STEP_MILLIS :u64 = 1000;
+
+static RELOAD :AtomicBool = ATOMIC_BOOL_INIT;
+static SHUTDOWN :AtomicBool = ATOMIC_BOOL_INIT;
+
+extern "C" fn on_reload(_signal :libc::c_int) {
+  RELOAD.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_shutdown(_signal :libc::c_int) {
+  SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+/// Install the handlers, SIGHUP requests to reload the configuration, SIGTERM and SIGINT to shut down
+pub fn listen() {
+  unsafe {
+    libc::signal(libc::SIGHUP, on_reload as *const () as libc::sighandler_t);
+    libc::signal(libc::SIGTERM, on_shutdown as *const () as libc::sighandler_t);
+    libc::signal(libc::SIGINT, on_shutdown as *const () as libc::sighandler_t);
+  }
+}
+
+/// True if SIGHUP was received since the last call
+pub fn reload_requested() -> bool {
+  RELOAD.swap(false, Ordering::SeqCst)
+}
+
+/// True after SIGTERM or SIGINT was received
+pub fn shutdown_requested() -> bool {
+  SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Sleep, but return early once a shutdown was requested. Returns false then.
+pub fn sleep(duration :Duration) -> bool {
+  sleep_unless(&SHUTDOWN, duration, Duration::from_millis(SLEEP_STEP_MILLIS))
+}
+
+fn sleep_unless(flag :&AtomicBool, duration :Duration, step :Duration) -> bool {
+  let mut left = duration;
+  while left > Duration::from_secs(0) {
+    if flag.load(Ordering::SeqCst) {
+      return false;
+    }
+    let nap = cmp::min(left, step);
+    thread::sleep(nap);
+    left -= nap;
+  }
+  !flag.load(Ordering::SeqCst)
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::{on_reload, reload_requested, sleep_unless};
+  use libc;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicBool, Ordering};
+  use std::thread;
+  use std::time::{Duration, Instant};
+
+  // The handlers are called directly, installing them would affect the whole test process
+
+  #[test]
+  fn reload() {
+    assert!(!reload_requested());
+
+    on_reload(libc::SIGHUP);
+    assert!(reload_requested());
+    assert!(!reload_requested());
+  }
+
+  #[test]
+  fn sleep() {
+    let flag = AtomicBool::new(false);
+    assert!(sleep_unless(&flag, Duration::from_millis(20), Duration::from_millis(5)));
+
+    flag.store(true, Ordering::SeqCst);
+    assert!(!sleep_unless(&flag, Duration::from_secs(60), Duration::from_millis(5)));
+  }
+
+  #[test]
+  fn sleep_interrupted() {
+    let flag = Arc::new(AtomicBool::new(false));
+    let setter = flag.clone();
+    thread::spawn(move || {
+      thread::sleep(Duration::from_millis(50));
+      setter.store(true, Ordering::SeqCst);
+    });
+
+    let start = Instant::now();
+    assert!(!sleep_unless(&flag, Duration::from_secs(60), Duration::from_millis(10)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+}
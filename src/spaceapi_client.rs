@@ -3,18 +3,73 @@
 extern crate spaceapi;
 use std::io::prelude::*;
 use rustc_serialize::json;
-use hyper::Client;
 use spaceapi::Optional::{self, Value, Absent};
 use spaceapi::{Status, Location};
+use hyper::status::StatusCode;
 
-use std::env;
-use std::fs::{self, File};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
 use std::io;
-use chrono::{DateTime, UTC};
+use std::sync::Arc;
+use std::time;
+use chrono::{DateTime, UTC, Duration};
+use feed_reader::{self, FeedEntry};
+use calendar::{self, Event};
+use config;
+use history::{self, History, Sample};
+use http_client::HttpClient;
+use storage;
+
+/// How long the title of a project page is cached
+const PROJECT_TITLE_TTL_HOURS :i64 = 24;
+/// How long to wait before retrying a project page without a title
+const PROJECT_TITLE_RETRY_HOURS :i64 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+  /// The server could not be reached or the connection broke
+  Network(String),
+  /// The server answered with something else than 2xx
+  HttpStatus(StatusCode),
+  /// The response is not what we expected, e.g. invalid JSON
+  Decode(String),
+  /// The server answered with an empty body, contains the url
+  EmptyResponse(String),
+  /// The SpaceAPI response contains none of these sensors
+  MissingSensor(String),
+  /// The SpaceAPI response advertises no feed of this kind
+  MissingFeed(String),
+  /// Writing a temporary file failed
+  Io(io::Error),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f :&mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Error::Network(ref e) => write!(f, "unable to connect to server, try again later:\nError: {}", e),
+      Error::HttpStatus(ref status) => write!(f, "server answered {}", status),
+      Error::Decode(ref e) => write!(f, "unable to parse server response: {}", e),
+      Error::EmptyResponse(ref url) => write!(f, "{} answered with an empty response", url),
+      Error::MissingSensor(ref name) => write!(f, "SpaceAPI response contains no {} sensors.", name),
+      Error::MissingFeed(ref kind) => write!(f, "SpaceAPI response contains no {} feed", kind),
+      Error::Io(ref e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(e :io::Error) -> Error {
+    Error::Io(e)
+  }
+}
 
 pub struct SpaceApiClient {
-  last_fetch: DateTime<UTC>,
+  /// None until the status was fetched successfully
+  last_fetch: Option<DateTime<UTC>>,
   status: spaceapi::Status,
+  project_titles: HashMap<String, (Option<String>, DateTime<UTC>)>,
+  http: Arc<HttpClient>,
 }
 
 impl SpaceApiClient {
@@ -23,8 +78,10 @@ impl SpaceApiClient {
     let empty_location = json::decode(r#"{ "lon": 0.0, "lat": 0.0 }"#).unwrap();
     let emtpy_contact = json::decode("{}").unwrap();
     SpaceApiClient{
-      last_fetch: UTC::now(),
+      last_fetch: None,
       status: Status::new("no space", "no logo", "no url", empty_location, emtpy_contact, vec![]),
+      project_titles: HashMap::new(),
+      http: Arc::new(HttpClient::new()),
     }
   }
   
@@ -32,148 +89,368 @@ impl SpaceApiClient {
   pub fn init() -> SpaceApiClient {
     let mut s = SpaceApiClient::new();
     
-    s.fetch_from_api();
+    if let Err(e) = s.fetch_from_api() {
+      warn!("Unable to fetch the status: {}", e);
+    }
     
     s
   }
   
-  pub fn fetch_aggregated_status(&mut self) -> ::std::result::Result<String, String> {
-    self.fetch_from_api();
+  pub fn fetch_aggregated_status(&mut self) -> Result<String, Error> {
+    try!(self.fetch_from_api());
+    
+    let reference = trend_reference(&trend_window());
+    self.aggregated_status(&reference)
+  }
+
+  /// The status fetched last, `reference` are the samples to compare with, see `trend_reference`
+  pub fn aggregated_status(&self, reference :&[Sample]) -> Result<String, Error> {
+    aggregate_status(self.status.clone(), reference, &trend_window())
+  }
+
+  /// Fetch the status and convert every sensor value into a history sample
+  pub fn fetch_sensor_samples(&mut self) -> Result<Vec<Sample>, Error> {
+    self.status = try!(fetch_status(&self.http));
+    let now = UTC::now();
+    self.last_fetch = Some(now);
     
-    aggregate_status(self.status.clone())
+    Ok(sensor_samples(&self.status, now.timestamp()))
+  }
+
+  /// Refresh the status. If that fails the previous one is kept,
+  /// only an error without any previous status is returned.
+  fn fetch_from_api(&mut self) -> Result<(), Error> {
+    let fetched = fetch_status(&self.http);
+    self.store_status(fetched)
   }
 
-  fn fetch_from_api(&mut self) {
-    if let Ok(status) = fetch_status() {
-      self.status = status;
-      self.last_fetch = UTC::now();
+  /// Keep the fetched status, see `fetch_from_api`.
+  /// Lets the status be fetched without holding on to the SpaceApiClient.
+  pub fn store_status(&mut self, fetched :Result<Status, Error>) -> Result<(), Error> {
+    match fetched {
+      Ok(status) => {
+        self.status = status;
+        self.last_fetch = Some(UTC::now());
+        Ok(())
+      },
+      Err(e) => match self.last_fetch {
+        Some(last_fetch) => {
+          warn!("Unable to fetch the status, using the one from {}: {}", last_fetch, e);
+          Ok(())
+        },
+        None => Err(e),
+      },
     }
   }
   
-  pub fn get_tmp_path_for_webcam(&self, url :&String) -> Result<String,io::Error> {
-    let dir = env::temp_dir().join("coredump_bot").join("get_tmp_path_for_webcam");
-    let path = env::temp_dir().join("coredump_bot").join("get_tmp_path_for_webcam").join( self.basename(url) );
-    let path = path.as_path();
-    
-    
-    try!(fs::create_dir_all(dir));
-    
-    let mut f = try!(File::create(&path));
-    
-    
-    let bin = try!(fetch_binary(url));
-    
-    
-    try!(f.write_all(&bin));
-    try!(f.sync_all());
-    
-    Ok::<String,io::Error>(format!("{}", path.to_str().unwrap()))
+  /// The client used for all requests, e.g. to download webcam pictures without holding on to the SpaceApiClient
+  pub fn http_client(&self) -> Arc<HttpClient> {
+    self.http.clone()
   }
   
   pub fn basename<'a>(&self, path :&'a String) -> &'a str {
-    match path.rfind('/') {
-      Some(p) => &path[p+1..],
-      None => path,
-    }
+    basename(path)
   }
   
   pub fn get_webcams(&self) -> Vec<String> {
-    match self.status.cam.clone() { // FIXME this clone is very ugly, because it should not be needed here.
-      Value(webcams) => webcams, // FIXME the clone should be here.
-      Absent => vec![],
-    }
+    webcams(&self.status)
   }
   
   pub fn get_location(&self) -> Location {
     self.status.location.clone()
   }
+  
+  pub fn get_space_name(&self) -> String {
+    self.status.space.clone()
+  }
+  
+  pub fn get_address(&self) -> String {
+    address(&self.status)
+  }
+  
+  pub fn get_location_text(&self) -> String {
+    location_text(&self.status)
+  }
+  
+  pub fn get_projects(&self) -> Vec<String> {
+    match self.status.projects {
+      Value(ref projects) => projects.clone(),
+      Absent => vec![],
+    }
+  }
+  
+  /// The cached title of a project page, None if it has to be fetched (again) with `fetch_project_title`
+  pub fn cached_project_title(&self, url :&String, now :&DateTime<UTC>) -> Option<Option<String>> {
+    match self.project_titles.get(url) {
+      Some(&(ref title, expires)) if expires > *now => Some(title.clone()),
+      _ => None,
+    }
+  }
+  
+  /// Pages without a title are retried earlier
+  pub fn cache_project_title(&mut self, url :String, title :Option<String>, now :&DateTime<UTC>) {
+    let ttl = if title.is_some() { PROJECT_TITLE_TTL_HOURS } else { PROJECT_TITLE_RETRY_HOURS };
+    self.project_titles.insert(url, (title, *now + Duration::hours(ttl)));
+  }
+  
+  /// URL of the feed advertised as `feeds.{kind}`, kind is one of "blog", "wiki", "calendar" or "flickr"
+  pub fn get_feed_url(&self, kind :&str) -> Option<String> {
+    let feeds = match self.status.feeds {
+      Value(ref feeds) => feeds,
+      Absent => return None,
+    };
+    let feed = match kind {
+      "blog" => &feeds.blog,
+      "wiki" => &feeds.wiki,
+      "calendar" => &feeds.calendar,
+      "flickr" => &feeds.flickr,
+      _ => return None,
+    };
+    
+    match *feed {
+      Value(ref feed) => Some(feed.url.clone()),
+      Absent => None,
+    }
+  }
+  
+  /// Fetch the entries of the blog feed advertised in the status
+  pub fn fetch_news(&mut self) -> Result<Vec<FeedEntry>, Error> {
+    try!(self.fetch_from_api());
+    
+    let url = try!(self.get_feed_url("blog").ok_or(Error::MissingFeed("blog".into())));
+    fetch_news(&self.http, &url)
+  }
+  
+  /// Fetch the events of the iCalendar feed advertised in the status
+  pub fn fetch_calendar(&mut self) -> Result<Vec<Event>, Error> {
+    try!(self.fetch_from_api());
+    
+    let url = try!(self.get_feed_url("calendar").ok_or(Error::MissingFeed("calendar".into())));
+    fetch_calendar(&self.http, &url)
+  }
 }
 
-/// Fetch the Status from https://status.crdmp.ch/
-fn fetch_status() -> Result<Status,String> {
-  let client = Client::new();
-
-  match client.get("https://status.crdmp.ch/").send() {
-    Err(e) => Err(format!("client.get() error:\nError: {}", e)),
-    Ok(mut res) => {
-      
-      let mut body = String::new();
-      match res.read_to_string(&mut body) {
-        Err(e) => { Err(format!("unable to connect to server, try again later:\nError: {}\nBody: {}", e, body)) },
-        Ok(len) => {
-          
-          match json::decode( &*body ) {
-            Err(e) => Err(format!("unable to parse server response of size {}: {:?}", len, e)),
-            Ok(status) => Ok(status),
-          }
-        }
-      }
-    }
+/// Entries of the blog feed at `url`
+pub fn fetch_news(http :&HttpClient, url :&String) -> Result<Vec<FeedEntry>, Error> {
+  let body = try!(http.get_text(url));
+  Ok(feed_reader::parse_feed(&body))
+}
+
+/// Events of the iCalendar feed at `url`
+pub fn fetch_calendar(http :&HttpClient, url :&String) -> Result<Vec<Event>, Error> {
+  let body = try!(http.get_text(url));
+  Ok(calendar::parse_calendar(&body))
+}
+
+/// Title of a project page, None if it has none or can not be fetched
+pub fn fetch_project_title(http :&HttpClient, url :&String) -> Option<String> {
+  match http.get_text(url) {
+    Ok(html) => html_title(&html),
+    Err(e) => {
+      warn!("fetch_project_title({}) Error: {}", url, e);
+      None
+    },
+  }
+}
+
+/// Recorded samples from the beginning of the trend `window`.
+/// Samples from its second half are left out, they would not cover the whole window.
+pub fn trend_reference(window :&time::Duration) -> Vec<Sample> {
+  let window = window.as_secs() as i64;
+  let now = UTC::now().timestamp();
+  
+  match History::open().read_all_since(now - window) {
+    Ok(samples) => samples.into_iter().filter(|s| s.timestamp <= now - window / 2).collect(),
+    Err(e) => {
+      warn!("unable to read the history for trends: {}", e);
+      vec![]
+    },
+  }
+}
+
+/// Download the webcam picture to a temporary location and return its path.
+/// The picture is only written again if the webcam reports a change,
+/// it is replaced as a whole so a picture being sent to another chat stays intact.
+pub fn get_tmp_path_for_webcam(http :&HttpClient, url :&String) -> Result<String,Error> {
+  let path = try!(storage::tmp_dir("get_tmp_path_for_webcam")).join( basename(url) );
+  let path = path.as_path();
+  
+  let (bin, modified) = try!(fetch_binary(http, url));
+  
+  if modified || !path.exists() {
+    try!(storage::replace_file(path, |tmp_path| File::create(tmp_path).and_then(|mut f| f.write_all(&bin))));
+  }
+  
+  Ok(format!("{}", path.to_str().unwrap()))
+}
+
+pub fn webcams(status :&Status) -> Vec<String> {
+  match status.cam {
+    Value(ref webcams) => webcams.clone(),
+    Absent => vec![],
+  }
+}
+
+/// Address of the space or the coordinates if the SpaceAPI contains no address
+pub fn address(status :&Status) -> String {
+  let location = &status.location;
+  match location.address {
+    Value(ref address) => address.clone(),
+    Absent => format!("{}, {}", location.lat, location.lon),
   }
 }
 
-/// Fetch a Binary from url and save it to a temporary Location.
-/// returns the temp Path
-fn fetch_binary(url :&String) -> Result<Vec<u8>,io::Error> {
-  let client = Client::new();
+/// Name, address and an OpenStreetMap link for clients which can not display a venue
+pub fn location_text(status :&Status) -> String {
+  let location = &status.location;
+  format!("{}\n{}\nhttps://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map=18/{lat}/{lon}",
+    status.space, address(status), lat=location.lat, lon=location.lon)
+}
+
+fn basename(path :&String) -> &str {
+  match path.rfind('/') {
+    Some(p) => &path[p+1..],
+    None => path,
+  }
+}
+
+/// Fetch the Status from the configured endpoint, https://status.crdmp.ch/ by default
+pub fn fetch_status(http :&HttpClient) -> Result<Status,Error> {
+  let url = config::get().status_url.clone();
+  let body = try!(http.get_text(&url));
+  if body.trim().is_empty() {
+    return Err(Error::EmptyResponse(url));
+  }
   
-  let mut res = match client.get(url).send() {
-    Ok(v) => v,
-    Err(e) => return Err(io::Error::new(io::ErrorKind::BrokenPipe, format!("{:?}", e))),
+  match json::decode( &*body ) {
+    Err(e) => Err(Error::Decode(format!("status of size {}: {:?}", body.len(), e))),
+    Ok(status) => Ok(status),
+  }
+}
+
+/// Content of the `<title>` element with the whitespace collapsed
+fn html_title(html :&str) -> Option<String> {
+  let start = match html.find("<title").or(html.find("<TITLE")) {
+    Some(p) => p,
+    None => return None,
+  };
+  let rest = &html[start..];
+  let rest = match rest.find('>') {
+    Some(p) => &rest[p+1..],
+    None => return None,
+  };
+  let end = match rest.find("</") {
+    Some(p) => p,
+    None => return None,
   };
   
-  let mut v = vec![];
-  let size = try!(res.read_to_end(&mut v));
+  let title = feed_reader::decode_text(&rest[..end]).split_whitespace().collect::<Vec<_>>().join(" ");
+  if title.is_empty() {
+    None
+  } else {
+    Some(title)
+  }
+}
+
+/// Fetch a Binary from url, the flag is false if it did not change since the last fetch.
+fn fetch_binary(http :&HttpClient, url :&String) -> Result<(Vec<u8>, bool),Error> {
+  let res = try!(http.get(url));
   
-  if size > 0 {
-    Ok(v)
+  if res.body.len() > 0 {
+    Ok((res.body, res.modified))
   } else {
-    Err(io::Error::new(io::ErrorKind::Interrupted, format!("fetch_binary({}) empty response", url)))
+    Err(Error::EmptyResponse(url.clone()))
   }
 }
 
 
-fn aggregate_status(status : Status) -> Result<String, String> {
+/// The values in /status are compared with the ones this long ago, see `space.trend_minutes` of the configuration
+pub fn trend_window() -> time::Duration {
+  time::Duration::from_secs(config::get().trend_minutes * 60)
+}
+
+/// `reference` are the samples from the beginning of the `window`, see `trend_reference`.
+/// A missing kind of sensor is mentioned in the text, the rest of the status is still shown.
+pub fn aggregate_status(status : Status, reference :&[Sample], window :&time::Duration) -> Result<String, Error> {
   let msg : Option<String> = status.state.message.into();
   let mut r = format!("{}\n\n", msg.unwrap_or( status.space ));
   
   if let Value(sensors) = status.sensors {
     let pnp = match extract_sensors(sensors.people_now_present, "people_now_present") {
       Ok(o) => {
-        o.into_iter().map(|e| {
-  format!("There are {n} people at {location}.\n", location=e.location.unwrap_or("unknown".into()), n=e.value)
+        o.into_iter().enumerate().map(|(i, e)| {
+          let trend = trend(reference, "people_now_present", i, e.value as f64, window, |delta| format!("{:.0}", delta));
+  format!("There are {n} people at {location}{trend}.\n", location=e.location.unwrap_or("unknown".into()), n=e.value, trend=trend)
         }).collect()
       },
-      Err(e) => e,
+      Err(e) => e.to_string(),
     };
     r = r + &pnp + "\n";
     
     let temp = match extract_sensors(sensors.temperature, "temperature") {
       Ok(o) => {
-        o.into_iter().map(|e| {
+        o.into_iter().enumerate().map(|(i, e)| {
           let name : Option<String> = e.name.into();
-          format!("{} ({}): {}{}\n", name.unwrap_or("Unidentified Sensor".into()), e.location, e.value, e.unit)
+          let trend = trend(reference, "temperature", i, e.value, window, |delta| format!("{:.1}", delta));
+          format!("{} ({}): {}{}{}\n", name.unwrap_or("Unidentified Sensor".into()), e.location, e.value, e.unit, trend)
         }).collect()
       },
-      Err(e) => e,
+      Err(e) => e.to_string(),
     };
     r = r + &temp;
     
   } else {
-    r = r + "SpaceAPI response contains no sensors";
-    return Err(r);
+    return Err(Error::MissingSensor("people_now_present or temperature".into()));
   }
   
-  
   Ok(r)
 }
 
-fn extract_sensors<T>(sensors : Optional<Vec<T>>, name : &str) -> Result<Vec<T>, String> {
+/// One sample per sensor, supported are people_now_present and temperature
+fn sensor_samples(status :&Status, timestamp :i64) -> Vec<Sample> {
+  let mut samples = vec![];
+  
+  if let Value(ref sensors) = status.sensors {
+    if let Value(ref pnp) = sensors.people_now_present {
+      for (index, sensor) in pnp.iter().enumerate() {
+        samples.push( Sample{ timestamp: timestamp, sensor: "people_now_present".into(), index: index as u64, value: sensor.value as f64, open: status.state.open } );
+      }
+    }
+    if let Value(ref temperature) = sensors.temperature {
+      for (index, sensor) in temperature.iter().enumerate() {
+        samples.push( Sample{ timestamp: timestamp, sensor: "temperature".into(), index: index as u64, value: sensor.value, open: status.state.open } );
+      }
+    }
+  }
+  
+  samples
+}
+
+/// e.g. " (↑2 in the last 1h)", empty if there is no reference value for the sensor
+fn trend<F: Fn(f64) -> String>(reference :&[Sample], sensor :&str, index :usize, value :f64, window :&time::Duration, format_delta :F) -> String {
+  let previous = match reference.iter().find(|s| s.sensor == sensor && s.index == index as u64) {
+    Some(sample) => sample.value,
+    None => return String::new(),
+  };
+  
+  let delta = value - previous;
+  let delta = if format_delta(delta.abs()) == format_delta(0.0) {
+    "→".into()
+  } else if delta > 0.0 {
+    format!("↑{}", format_delta(delta))
+  } else {
+    format!("↓{}", format_delta(-delta))
+  };
+  
+  format!(" ({} in the last {})", delta, history::describe_duration(window))
+}
+
+fn extract_sensors<T>(sensors : Optional<Vec<T>>, name : &str) -> Result<Vec<T>, Error> {
   match sensors {
-    Absent => Err(format!("SpaceAPI response contains no {} sensors.", name)),
+    Absent => Err(Error::MissingSensor(name.into())),
     Value(sensors) => {
       if sensors.is_empty() {
-        Err(format!("SpaceAPI response has an empty list of {} sensors.", name))
+        Err(Error::MissingSensor(name.into()))
       } else {
         Ok(sensors)
       }
@@ -205,7 +482,9 @@ fn extract_sensors<T>(sensors : Optional<Vec<T>>, name : &str) -> Result<Vec<T>,
 
 #[cfg(test)]
 mod test {
-  use super::{SpaceApiClient, aggregate_status};
+  use super::{SpaceApiClient, aggregate_status, sensor_samples};
+  use std::time::Duration;
+  use history::Sample;
   use spaceapi::{Status, Location, Contact};
   use spaceapi::optional::Optional;
   use spaceapi::sensors::{TemperatureSensor, PeopleNowPresentSensor};
@@ -233,24 +512,115 @@ mod test {
   
   #[test]
   fn aggregate_status_closed() {
-    let n = aggregate_status( good_response() );
+    let n = aggregate_status( good_response(), &[], &Duration::from_secs(60*60) ).map_err(|e| e.to_string());
     
     assert_eq!( Ok("Open every Monday from 20:00\n\nThere are 0 people at Hackerspace.\n\nRaspberry CPU (Hackerspace): 55.7\u{b0}C\n".into()), n );
   }
   
   #[test]
   fn aggregate_status_6() {
-    let n = aggregate_status( minimal_response() );
+    let n = aggregate_status( minimal_response(), &[], &Duration::from_secs(60*60) ).map_err(|e| e.to_string());
     
     assert_eq!( Ok("Open every Monday from 20:00\n\nSpaceAPI response contains no people_now_present sensors.\nRaspberry CPU (Hackerspace): 55.7\u{b0}C\n".into()), n );
   }
   
   #[test]
   fn aggregate_status_err() {
-    let n = aggregate_status( cam_response() );
+    let n = aggregate_status( cam_response(), &[], &Duration::from_secs(60*60) ).map_err(|e| e.to_string());
     
     assert_eq!( Ok("6 people here right now\n\nThere are 6 people at Hackerspace.\n\nRaspberry CPU (Hackerspace): 48.7\u{b0}C\n".into()), n );
   }
+  
+  #[test]
+  fn aggregate_status_no_sensors() {
+    let mut status = good_response();
+    status.sensors = Optional::Absent;
+    let n = aggregate_status( status, &[], &Duration::from_secs(60*60) ).map_err(|e| e.to_string());
+    
+    assert_eq!( Err("SpaceAPI response contains no people_now_present or temperature sensors.".into()), n );
+  }
+  
+  #[test]
+  fn aggregate_status_trend() {
+    let reference = vec![
+      Sample{ timestamp: 42, sensor: "people_now_present".into(), index: 0, value: 4.0, open: Some(true) },
+      Sample{ timestamp: 42, sensor: "temperature".into(), index: 0, value: 55.7, open: Some(true) },
+    ];
+    let n = aggregate_status( cam_response(), &reference, &Duration::from_secs(60*60) ).map_err(|e| e.to_string());
+    
+    assert_eq!( Ok("6 people here right now\n\nThere are 6 people at Hackerspace (\u{2191}2 in the last 1h).\n\nRaspberry CPU (Hackerspace): 48.7\u{b0}C (\u{2193}7.0 in the last 1h)\n".into()), n );
+  }
+  
+  #[test]
+  fn aggregate_status_unchanged() {
+    let reference = vec![
+      Sample{ timestamp: 42, sensor: "temperature".into(), index: 0, value: 48.72, open: Some(true) },
+    ];
+    let n = aggregate_status( cam_response(), &reference, &Duration::from_secs(30*60) ).map_err(|e| e.to_string());
+    
+    assert_eq!( Ok("6 people here right now\n\nThere are 6 people at Hackerspace.\n\nRaspberry CPU (Hackerspace): 48.7\u{b0}C (\u{2192} in the last 30min)\n".into()), n );
+  }
+  
+  #[test]
+  fn samples() {
+    assert_eq!( vec![
+      Sample{ timestamp: 42, sensor: "people_now_present".into(), index: 0, value: 6.0, open: Some(true) },
+      Sample{ timestamp: 42, sensor: "temperature".into(), index: 0, value: 48.7, open: Some(true) },
+    ], sensor_samples( &cam_response(), 42 ) );
+  }
+  
+  #[test]
+  fn samples_minimal() {
+    assert_eq!( vec![
+      Sample{ timestamp: 42, sensor: "temperature".into(), index: 0, value: 55.7, open: Some(false) },
+    ], sensor_samples( &minimal_response(), 42 ) );
+  }
+}
+
+
+#[cfg(test)]
+mod test_html_title {
+  use super::html_title;
+
+  #[test]
+  fn title() {
+    assert_eq!(Some("Projekte \u{2013} Coredump".into()), html_title("<html><head>\n<title>\n  Projekte &#8211;\n  Coredump</title></head></html>"));
+  }
+
+  #[test]
+  fn uppercase() {
+    assert_eq!(Some("Coredump".into()), html_title("<HTML><HEAD><TITLE>Coredump</TITLE></HEAD></HTML>"));
+  }
+
+  #[test]
+  fn missing() {
+    assert_eq!(None, html_title("<html><head></head></html>"));
+    assert_eq!(None, html_title("<html><head><title> </title></head></html>"));
+  }
+}
+
+
+#[cfg(test)]
+mod test_location {
+  use super::SpaceApiClient;
+  use rustc_serialize::json;
+
+  #[test]
+  fn text() {
+    let mut sac = SpaceApiClient::new();
+    sac.status.space = "coredump".into();
+    sac.status.location = json::decode(r#"{ "address": "Spinnereistrasse 2, 8640 Rapperswil, Switzerland", "lat": 47.22936, "lon": 8.82949 }"#).unwrap();
+    
+    assert_eq!("coredump\nSpinnereistrasse 2, 8640 Rapperswil, Switzerland\nhttps://www.openstreetmap.org/?mlat=47.22936&mlon=8.82949#map=18/47.22936/8.82949", sac.get_location_text());
+  }
+
+  #[test]
+  fn without_address() {
+    let mut sac = SpaceApiClient::new();
+    sac.status.location = json::decode(r#"{ "lat": 47.22936, "lon": 8.82949 }"#).unwrap();
+    
+    assert_eq!("47.22936, 8.82949", sac.get_address());
+  }
 }
 
 
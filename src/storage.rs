@@ -0,0 +1,122 @@
+//! Persist small pieces of state between restarts
+
+use config;
+use libc;
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use rustc_serialize::{json, Decodable, Encodable};
+
+/// Numbers the temporary files of `replace_file`
+static REPLACEMENTS :AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Directory holding the state, defaults to `./data`, see `storage.data_dir` of the configuration
+pub fn data_dir() -> PathBuf {
+  config::get().data_dir.clone()
+}
+
+/// Directory for temporary files like webcam pictures, created if it does not exist
+pub fn tmp_dir(purpose :&str) -> Result<PathBuf, io::Error> {
+  let dir = env::temp_dir().join(&config::get().temp_dir_name).join(purpose);
+  try!(fs::create_dir_all(&dir));
+  Ok(dir)
+}
+
+/// Load `{name}.json` from the data directory.
+/// Returns None if the file does not exist or can not be decoded.
+pub fn load<T: Decodable>(name :&str) -> Option<T> {
+  let path = data_dir().join(format!("{}.json", name));
+
+  let mut body = String::new();
+  if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut body)) {
+    if e.kind() != io::ErrorKind::NotFound {
+      warn!("storage::load({:?}) Error: {}", path, e);
+    }
+    return None;
+  }
+
+  match json::decode(&body) {
+    Ok(value) => Some(value),
+    Err(e) => {
+      warn!("storage::load({:?}) unable to decode: {:?}", path, e);
+      None
+    },
+  }
+}
+
+/// Write `{name}.json` to the data directory.
+/// The file is written next to the target first and renamed afterwards so a crash never leaves half a file.
+pub fn save<T: Encodable>(name :&str, value :&T) -> Result<(), io::Error> {
+  let dir = data_dir();
+  let path = dir.join(format!("{}.json", name));
+  let tmp_path = dir.join(format!(".{}.json.tmp", name));
+
+  let body = try!(json::encode(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))));
+
+  try!(fs::create_dir_all(&dir));
+  {
+    let mut f = try!(File::create(&tmp_path));
+    try!(f.write_all(body.as_bytes()));
+    try!(f.sync_all());
+  }
+  fs::rename(&tmp_path, &path)
+}
+
+/// Let `write` create a file next to `path` and rename it to `path` afterwards,
+/// so a reader of `path` never sees half a file, even if several threads replace it.
+/// The temporary file keeps the extension, e.g. for image formats chosen by it.
+pub fn replace_file<F>(path :&Path, write :F) -> Result<(), io::Error> where F: FnOnce(&Path) -> Result<(), io::Error> {
+  let name = try!(path.file_name().ok_or(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is no file", path.display()))));
+  let pid = unsafe { libc::getpid() };
+  let tmp_path = path.with_file_name(format!(".{}.{}.{}", pid, REPLACEMENTS.fetch_add(1, Ordering::SeqCst), name.to_string_lossy()));
+
+  if let Err(e) = write(&tmp_path) {
+    let _ = fs::remove_file(&tmp_path);
+    return Err(e);
+  }
+  fs::rename(&tmp_path, path)
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::replace_file;
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::prelude::*;
+  use std::io;
+
+  #[test]
+  fn replace() {
+    let dir = env::temp_dir().join("coredump_bot_storage_test");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("picture.jpg");
+
+    replace_file(&path, |tmp_path| {
+      assert_eq!(Some("jpg"), tmp_path.extension().and_then(|e| e.to_str()));
+      File::create(tmp_path).and_then(|mut f| f.write_all(b"first"))
+    }).unwrap();
+    // A reader which opened the file keeps its content
+    let mut reader = File::open(&path).unwrap();
+    replace_file(&path, |tmp_path| File::create(tmp_path).and_then(|mut f| f.write_all(b"second"))).unwrap();
+
+    let mut content = String::new();
+    reader.read_to_string(&mut content).unwrap();
+    assert_eq!("first", content);
+    content.clear();
+    File::open(&path).unwrap().read_to_string(&mut content).unwrap();
+    assert_eq!("second", content);
+
+    let failed = replace_file(&path, |tmp_path| {
+      try!(File::create(tmp_path));
+      Err(io::Error::new(io::ErrorKind::Other, "failed"))
+    });
+    assert!(failed.is_err());
+    assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+  }
+}
@@ -0,0 +1,98 @@
+//! Chats receiving announcements like new blog articles or reminders of events
+
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+use std::io;
+use std::sync::Mutex;
+
+/// Stored as a plain array of chat ids
+#[derive(Debug, Default, PartialEq)]
+pub struct Subscriptions {
+  chats :Vec<i64>,
+}
+
+impl Subscriptions {
+  /// Returns false if the chat was already subscribed
+  pub fn subscribe(&mut self, chat_id :i64) -> bool {
+    if self.chats.contains(&chat_id) {
+      false
+    } else {
+      self.chats.push(chat_id);
+      true
+    }
+  }
+
+  /// Returns false if the chat was not subscribed
+  pub fn unsubscribe(&mut self, chat_id :i64) -> bool {
+    let before = self.chats.len();
+    self.chats.retain(|id| *id != chat_id);
+    before != self.chats.len()
+  }
+
+  pub fn chats(&self) -> Vec<i64> {
+    self.chats.clone()
+  }
+}
+
+impl Encodable for Subscriptions {
+  fn encode<S: Encoder>(&self, s :&mut S) -> Result<(), S::Error> {
+    self.chats.encode(s)
+  }
+}
+
+impl Decodable for Subscriptions {
+  fn decode<D: Decoder>(d :&mut D) -> Result<Subscriptions, D::Error> {
+    Ok( Subscriptions{ chats: try!(Vec::decode(d)) } )
+  }
+}
+
+/// State holding subscriptions, like the news or the events
+pub trait Subscribed {
+  fn subscriptions(&mut self) -> &mut Subscriptions;
+  fn save(&self) -> Result<(), io::Error>;
+}
+
+/// Subscribe or unsubscribe the chat and save the state if it changed.
+/// Returns false if the chat already was or was not subscribed.
+pub fn set<T: Subscribed>(state :&Mutex<T>, chat_id :i64, subscribed :bool) -> bool {
+  let mut state = state.lock().unwrap();
+  let changed = if subscribed {
+    state.subscriptions().subscribe(chat_id)
+  } else {
+    state.subscriptions().unsubscribe(chat_id)
+  };
+
+  if changed {
+    if let Err(e) = state.save() {
+      warn!("Unable to save subscriptions: {}", e);
+    }
+  }
+  changed
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::Subscriptions;
+  use rustc_serialize::json;
+
+  #[test]
+  fn subscribe() {
+    let mut subscriptions = Subscriptions::default();
+
+    assert!(subscriptions.subscribe(42));
+    assert!(subscriptions.subscribe(42) == false);
+    assert_eq!(vec![42], subscriptions.chats());
+    assert!(subscriptions.unsubscribe(42));
+    assert!(subscriptions.unsubscribe(42) == false);
+  }
+
+  #[test]
+  fn stored_as_array() {
+    let mut subscriptions = Subscriptions::default();
+    subscriptions.subscribe(-7);
+
+    assert_eq!("[-7]", json::encode(&subscriptions).unwrap());
+    assert_eq!(subscriptions, json::decode("[-7]").unwrap());
+  }
+}
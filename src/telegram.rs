@@ -0,0 +1,46 @@
+//! Telegram frontend: passes the messages to the `Handler` and sends its replies through the `Outbox`
+
+use commands::{Context, Handler, Reply};
+use config;
+use outbox::{Outbox, Outgoing};
+use telegram_bot::types::ParseMode;
+use telegram_bot::{Error, Message, MessageType};
+use user_input_compiler::Input;
+
+/// Answer a single message
+pub fn handle_message(outbox :&Outbox, handler :&Handler, m :Message) -> Result<(), Error> {
+  let context = Context{ chat_id: m.chat.id(), private: m.chat.is_user(), pictures: true, user_id: Some(m.from.id) };
+  let name = m.from.first_name;
+
+  let replies = match m.msg {
+    MessageType::Text(t) => {
+      info!("<{}> {}", name, t);
+      // In groups commands may be addressed to the bot
+      let t = t.replace(&format!("@{}", config::get().username), "");
+      handler.handle(&context, Input::from(t.trim().to_string()))
+    },
+    MessageType::Location(shared) => {
+      info!("<{}> shared a location", name);
+      handler.handle_location(&context, shared.latitude as f64, shared.longitude as f64)
+    },
+    _ => handler.handle_unknown(&context),
+  };
+
+  send_replies(outbox, context.chat_id, replies)
+}
+
+pub fn send_replies(outbox :&Outbox, chat_id :i64, replies :Vec<Reply>) -> Result<(), Error> {
+  for reply in replies {
+    let outgoing = match reply {
+      Reply::Text(text) => Outgoing::Text{ text: text, parse_mode: None },
+      Reply::Markdown(text) => Outgoing::Text{ text: text, parse_mode: Some(ParseMode::Markdown) },
+      Reply::Photo{ path, caption } => Outgoing::Photo{ path: path, caption: Some(caption) },
+      Reply::Document(path) => Outgoing::Document{ path: path },
+      Reply::Venue{ latitude, longitude, title, address, text } =>
+        Outgoing::Venue{ latitude: latitude, longitude: longitude, title: title, address: address, text: text },
+    };
+    try!(outbox.send(chat_id, outgoing));
+  }
+
+  Ok(())
+}
@@ -0,0 +1,166 @@
+//! Remember which updates of Telegram were processed, so a restart neither loses nor repeats commands
+
+use storage;
+use std::cmp;
+use std::io;
+
+/// How many update_ids are remembered to recognize repeated updates
+const RECENT_UPDATES :usize = 1000;
+
+#[derive(RustcEncodable, RustcDecodable, Default)]
+struct UpdatesState {
+  /// update_id of the next update to fetch, 0 lets Telegram start with the oldest unconfirmed one
+  offset :i64,
+  /// update_ids processed last, the oldest first
+  recent :Vec<i64>,
+}
+
+pub struct Updates {
+  state :UpdatesState,
+  /// update_ids handed over but not answered yet, Telegram delivers them again after a restart
+  pending :Vec<i64>,
+}
+
+impl Updates {
+  /// Restore the offset from the data directory
+  pub fn load() -> Updates {
+    Updates{
+      state: storage::load("updates").unwrap_or(UpdatesState::default()),
+      pending: vec![],
+    }
+  }
+
+  /// Saves the offset before the first pending update, so the unanswered ones are not confirmed
+  pub fn save(&self) -> Result<(), io::Error> {
+    storage::save("updates", &UpdatesState{ offset: self.offset().unwrap_or(0), recent: self.state.recent.clone() })
+  }
+
+  /// The offset for getUpdates, confirms all updates before it to Telegram.
+  /// It stays at the first pending update until that is answered.
+  pub fn offset(&self) -> Option<i64> {
+    let offset = match self.pending.iter().min() {
+      Some(&pending) if self.state.offset == 0 || pending < self.state.offset => pending,
+      _ => self.state.offset,
+    };
+
+    if offset == 0 {
+      None
+    } else {
+      Some(offset)
+    }
+  }
+
+  /// True if the update is pending or was answered already
+  pub fn seen(&self, update_id :i64) -> bool {
+    self.pending.contains(&update_id) || self.state.recent.contains(&update_id)
+  }
+
+  /// Returns false if the update was received already and has to be skipped, otherwise it is pending until `answered`.
+  /// Only recently seen update_ids count: the webhook receives updates in parallel and out of order,
+  /// and after a week without updates Telegram starts again with a random, possibly lower update_id.
+  pub fn receive(&mut self, update_id :i64) -> bool {
+    if self.seen(update_id) {
+      return false;
+    }
+
+    self.pending.push(update_id);
+    true
+  }
+
+  /// The update is processed and may be confirmed to Telegram
+  pub fn answered(&mut self, update_id :i64) {
+    self.pending.retain(|id| *id != update_id);
+
+    self.state.recent.push(update_id);
+    if self.state.recent.len() > RECENT_UPDATES {
+      self.state.recent.remove(0);
+    }
+    self.state.offset = cmp::max(self.state.offset, update_id + 1);
+  }
+}
+
+
+#[cfg(test)]
+mod test {
+  use super::{Updates, UpdatesState};
+
+  fn updates() -> Updates {
+    Updates{ state: UpdatesState::default(), pending: vec![] }
+  }
+
+  /// Receive and answer right away
+  fn handle(updates :&mut Updates, update_id :i64) -> bool {
+    let received = updates.receive(update_id);
+    if received {
+      updates.answered(update_id);
+    }
+    received
+  }
+
+  #[test]
+  fn confirm() {
+    let mut updates = updates();
+    assert_eq!(None, updates.offset());
+
+    assert!(handle(&mut updates, 10));
+    assert!(handle(&mut updates, 12));
+    assert_eq!(Some(13), updates.offset());
+    assert!(handle(&mut updates, 12) == false);
+    assert!(handle(&mut updates, 10) == false);
+    assert_eq!(Some(13), updates.offset());
+
+    // An update answered late does not move the offset back
+    assert!(handle(&mut updates, 11));
+    assert_eq!(Some(13), updates.offset());
+  }
+
+  #[test]
+  fn out_of_order() {
+    let mut updates = updates();
+
+    assert!(handle(&mut updates, 12));
+    assert!(handle(&mut updates, 11));
+    assert!(handle(&mut updates, 12) == false);
+    assert!(handle(&mut updates, 11) == false);
+    assert_eq!(Some(13), updates.offset());
+  }
+
+  #[test]
+  fn pending() {
+    let mut updates = updates();
+    assert!(handle(&mut updates, 9));
+
+    // Not confirmed to Telegram before it is answered, but not handed over twice either
+    assert!(updates.receive(10));
+    assert!(updates.receive(11));
+    assert_eq!(Some(10), updates.offset());
+    assert!(updates.receive(10) == false);
+
+    updates.answered(11);
+    assert_eq!(Some(10), updates.offset());
+    updates.answered(10);
+    assert_eq!(Some(12), updates.offset());
+  }
+
+  #[test]
+  fn reset() {
+    let mut updates = updates();
+    assert!(handle(&mut updates, 500000));
+
+    // Telegram started again with a random update_id
+    assert!(handle(&mut updates, 1234));
+    assert!(handle(&mut updates, 1235));
+    assert_eq!(Some(500001), updates.offset());
+  }
+
+  #[test]
+  fn forget_old_updates() {
+    let mut updates = updates();
+    for update_id in 0..super::RECENT_UPDATES as i64 + 1 {
+      assert!(handle(&mut updates, update_id));
+    }
+
+    assert_eq!(super::RECENT_UPDATES, updates.state.recent.len());
+    assert!(handle(&mut updates, 0));
+  }
+}
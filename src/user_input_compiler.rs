@@ -6,7 +6,7 @@
 //! 
 //! ```
 //! Command         := "/" CommandWord
-//! CommandWord     := Status | Subscribe | Cancel | Version | Help | WebCam | Start | Grammar | Location | InvalidSyntax
+//! CommandWord     := Status | Subscribe | Cancel | Version | Help | WebCam | Start | Grammar | Location | News | Events | Projects | History | Chart | Forecast | Export | Reload | InvalidSyntax
 //! Status          := "status"
 //! Subscribe       := "subscribe" SensorSelector Duration
 //! SensorSelector  := SensorString OptionalInteger
@@ -22,7 +22,20 @@
 //! WebCam          := "webcam" OptionalInteger
 //! Start           := "start"
 //! Grammar         := "grammar"
-//! Location        := "location"
+//! Location        := "location" LocationFormat
+//! LocationFormat  := "text" | ɛ
+//! News            := "news" Toggle | "news" OptionalInteger
+//! Events          := "events" Toggle | "events" OptionalInteger
+//! Projects        := "projects"
+//! History         := "history" SensorSelector Duration
+//! Chart           := "chart" SensorSelector Duration
+//! Forecast        := "forecast" OptionalWeekday
+//! Export          := "export" SensorSelector Duration ExportFormat
+//! ExportFormat    := "csv" | "json" | ɛ
+//! Reload          := "reload"
+//! OptionalWeekday := Weekday | ɛ
+//! Weekday         := "monday" | "mon" | "tuesday" | "tue" | "wednesday" | "wed" | "thursday" | "thu" | "friday" | "fri" | "saturday" | "sat" | "sunday" | "sun"
+//! Toggle          := "on" | "off"
 //! InvalidSyntax   := *
 //! ```
 
@@ -30,6 +43,7 @@
 
 use std::time::Duration;
 use std::str::Chars;
+use chrono::Weekday;
 
 #[derive(Debug, PartialEq)]
 pub enum Input {
@@ -42,6 +56,18 @@ pub enum Input {
   Start,
   Grammar,
   Location,
+  LocationText,
+  News{ count :Option<usize> },
+  NewsSubscription{ enabled :bool },
+  Events{ days :Option<u64> },
+  EventsSubscription{ enabled :bool },
+  Projects,
+  History{ sensor :SensorSelector, duration :Duration },
+  Chart{ sensor :SensorSelector, duration :Duration },
+  Forecast{ weekday :Option<Weekday> },
+  Export{ sensor :SensorSelector, duration :Duration, format :ExportFormat },
+  /// Read the configuration again, only for admins
+  Reload,
   InvalidSyntax( String ),
 }
 #[derive(Debug, PartialEq)]
@@ -49,6 +75,34 @@ pub struct SensorSelector {
   sensor_selector :String,
   nth :Option<u64>,
 }
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExportFormat {
+  Csv,
+  Json,
+}
+
+impl ExportFormat {
+  /// File extension without the dot
+  pub fn extension(&self) -> &'static str {
+    match *self {
+      ExportFormat::Csv => "csv",
+      ExportFormat::Json => "json",
+    }
+  }
+}
+
+impl SensorSelector {
+  /// The SensorString, e.g. "temperature"
+  pub fn sensor(&self) -> &str {
+    &self.sensor_selector
+  }
+  
+  /// The OptionalInteger, the first sensor if it was omitted
+  pub fn index(&self) -> u64 {
+    self.nth.unwrap_or(0)
+  }
+}
 use self::Input::*;
 
 /// like try! but unwraps the Error
@@ -120,10 +174,77 @@ fn match_command_word(s :&mut Chars) -> Input {
     return Grammar;
   } else 
   
-  if starts_with(s, "location") {
+  if matches_with(s, "location") {
+    consume_whitespaces(s);
+    if starts_with(s, "text") {
+      return LocationText;
+    }
     return Location;
   } else 
   
+  if matches_with(s, "news") {
+    if let Some(enabled) = match_toggle(s) {
+      return NewsSubscription{ enabled: enabled };
+    }
+    let count = match match_full_integer(s) {
+      Ok(n) if n > 0 => Some(n as usize),
+      Ok(_) => return InvalidSyntax("Expected positive Integer".into()),
+      Err(_) => None,
+    };
+    return News{ count: count };
+  } else 
+  
+  if matches_with(s, "events") {
+    if let Some(enabled) = match_toggle(s) {
+      return EventsSubscription{ enabled: enabled };
+    }
+    let days = match match_full_integer(s) {
+      Ok(n) if n > 0 => Some(n as u64),
+      Ok(_) => return InvalidSyntax("Expected positive Integer".into()),
+      Err(_) => None,
+    };
+    return Events{ days: days };
+  } else 
+  
+  if starts_with(s, "projects") {
+    return Projects;
+  } else 
+  
+  if matches_with(s, "history") {
+    let sensor = extract!(match_sensor_selector(s));
+    let duration = extract!(match_duration(s));
+    return History{ sensor: sensor, duration: duration };
+  } else 
+  
+  if matches_with(s, "chart") {
+    let sensor = extract!(match_sensor_selector(s));
+    let duration = extract!(match_duration(s));
+    return Chart{ sensor: sensor, duration: duration };
+  } else 
+  
+  if matches_with(s, "forecast") {
+    let weekday = extract!(match_optional_weekday(s));
+    return Forecast{ weekday: weekday };
+  } else 
+  
+  if matches_with(s, "export") {
+    let sensor = extract!(match_sensor_selector(s));
+    let duration = extract!(match_duration(s));
+    consume_whitespaces(s);
+    let format = if starts_with(s, "json") {
+      ExportFormat::Json
+    } else if starts_with(s, "csv") || s.clone().next().is_none() {
+      ExportFormat::Csv
+    } else {
+      return InvalidSyntax("Expected csv or json".into());
+    };
+    return Export{ sensor: sensor, duration: duration, format: format };
+  } else 
+  
+  if starts_with(s, "reload") {
+    return Reload;
+  } else 
+  
   if starts_with(s, "start") {
     return Start;
   } else {
@@ -306,19 +427,54 @@ fn collect_integer(s :&mut Chars) -> Result<String, Input> {
 fn match_timesuffix(s :&mut Chars) -> Result<i64, Input> {
   consume_whitespaces(s);
   
-  if starts_with(s, "m") || starts_with(s, "min") {
+  if matches_with(s, "min") || matches_with(s, "m") {
     return Ok(60);
   }
-  if starts_with(s, "h") {
+  if matches_with(s, "h") {
     return Ok(60*60);
   }
-  if starts_with(s, "d") {
+  if matches_with(s, "d") {
     return Ok(60*60*24);
   }
   
   Err( InvalidSyntax(format!("Invalid TimeSuffix")) )
 }
 
+/// Toggle          := "on" | "off"
+/// Only advances the Iterator on a match
+fn match_toggle(s :&mut Chars) -> Option<bool> {
+  let mut it = s.clone();
+  consume_whitespaces(&mut it);
+  
+  let enabled = if matches_with(&mut it, "off") {
+    false
+  } else if matches_with(&mut it, "on") {
+    true
+  } else {
+    return None;
+  };
+  
+  *s = it;
+  Some(enabled)
+}
+
+fn match_optional_weekday(s :&mut Chars) -> Result<Option<Weekday>, Input> {
+  consume_whitespaces(s);
+  let word :String = s.take_while(|c| c.is_alphabetic()).collect::<String>().to_lowercase();
+  
+  let weekday = match &*word {
+    "" => return Ok(None),
+    "monday" | "mon" => Weekday::Mon,
+    "tuesday" | "tue" => Weekday::Tue,
+    "wednesday" | "wed" => Weekday::Wed,
+    "thursday" | "thu" => Weekday::Thu,
+    "friday" | "fri" => Weekday::Fri,
+    "saturday" | "sat" => Weekday::Sat,
+    "sunday" | "sun" => Weekday::Sun,
+    _ => return Err( InvalidSyntax(format!("Unknown Weekday {:?}", word)) ),
+  };
+  Ok(Some(weekday))
+}
 
 /// Search without modifing the Iterator
 fn starts_with(haystack_iter :&Chars, needle :&str) -> bool {
@@ -666,4 +822,103 @@ mod test {
   fn location() {
     assert_eq!( Location, Input::from( format!("/location") ) )
   }
+  
+  #[test]
+  fn location_text() {
+    assert_eq!( LocationText, Input::from( format!("/location text") ) )
+  }
+  
+  
+  #[test]
+  fn news() {
+    assert_eq!( News{ count: None }, Input::from( format!("/news") ) )
+  }
+  
+  #[test]
+  fn news_5() {
+    assert_eq!( News{ count: Some(5) }, Input::from( format!("/news 5") ) )
+  }
+  
+  #[test]
+  fn news_0() {
+    assert_eq!( InvalidSyntax("Expected positive Integer".into()), Input::from( format!("/news 0") ) )
+  }
+  
+  #[test]
+  fn news_on() {
+    assert_eq!( NewsSubscription{ enabled: true }, Input::from( format!("/news on") ) )
+  }
+  
+  #[test]
+  fn news_off() {
+    assert_eq!( NewsSubscription{ enabled: false }, Input::from( format!("/news  off") ) )
+  }
+  
+  
+  #[test]
+  fn events() {
+    assert_eq!( Events{ days: None }, Input::from( format!("/events") ) )
+  }
+  
+  #[test]
+  fn events_30() {
+    assert_eq!( Events{ days: Some(30) }, Input::from( format!("/events 30") ) )
+  }
+  
+  #[test]
+  fn events_on() {
+    assert_eq!( EventsSubscription{ enabled: true }, Input::from( format!("/events on") ) )
+  }
+  
+  
+  #[test]
+  fn projects() {
+    assert_eq!( Projects, Input::from( format!("/projects") ) )
+  }
+  
+  
+  #[test]
+  fn reload() {
+    assert_eq!( Reload, Input::from( format!("/reload") ) )
+  }
+  
+  
+  #[test]
+  fn history_temperature_2h() {
+    assert_eq!( History{ sensor: SensorSelector{ sensor_selector: "temperature".into(), nth: None }, duration: Duration::from_secs(2*60*60) }
+        , Input::from( format!("/history temperature 2h") ) )
+  }
+  
+  #[test]
+  fn history_pnp_1_7d() {
+    assert_eq!( History{ sensor: SensorSelector{ sensor_selector: "people_now_present".into(), nth: Some(1) }, duration: Duration::from_secs(7*60*60*24) }
+        , Input::from( format!("/history people_now_present 1 7d") ) )
+  }
+  
+  #[test]
+  fn history_missing_duration() {
+    assert_eq!( InvalidSyntax("Invalid Integer".into()), Input::from( format!("/history temperature") ) )
+  }
+  
+  #[test]
+  fn chart_pnp_1d() {
+    assert_eq!( Chart{ sensor: SensorSelector{ sensor_selector: "people_now_present".into(), nth: None }, duration: Duration::from_secs(60*60*24) }
+        , Input::from( format!("/chart people_now_present 1d") ) )
+  }
+  
+  #[test]
+  fn forecast() {
+    assert_eq!( Forecast{ weekday: None }, Input::from( format!("/forecast") ) );
+    assert_eq!( Forecast{ weekday: Some(Weekday::Mon) }, Input::from( format!("/forecast monday") ) );
+    assert_eq!( Forecast{ weekday: Some(Weekday::Fri) }, Input::from( format!("/forecast Fri") ) );
+    assert_eq!( InvalidSyntax("Unknown Weekday \"someday\"".into()), Input::from( format!("/forecast someday") ) );
+  }
+  
+  #[test]
+  fn export() {
+    let sensor = || SensorSelector{ sensor_selector: "temperature".into(), nth: None };
+    assert_eq!( Export{ sensor: sensor(), duration: Duration::from_secs(60*60*24*7), format: ExportFormat::Csv }, Input::from( format!("/export temperature 7d") ) );
+    assert_eq!( Export{ sensor: sensor(), duration: Duration::from_secs(60*60*24*7), format: ExportFormat::Json }, Input::from( format!("/export temperature 7d json") ) );
+    assert_eq!( InvalidSyntax("Expected csv or json".into()), Input::from( format!("/export temperature 7d xml") ) );
+  }
 }
@@ -0,0 +1,129 @@
+//! Receive the updates of Telegram over HTTP(S) instead of long polling
+//!
+//! Telegram POSTs every update as JSON to the registered URL, see https://core.telegram.org/bots/api#setwebhook.
+//! The URL ends with a secret path, requests to any other path are refused.
+
+use config::Webhook;
+use hyper::method::Method;
+use hyper::net::Openssl;
+use hyper::server::{Server, Request, Response, Listening};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use hyper;
+use rustc_serialize::json;
+use telegram_bot::Update;
+
+use std::io::prelude::*;
+
+/// Start the server, `handler` is called for every update on the threads of the server.
+/// If it returns false the update is refused and Telegram delivers it again later, e.g. while shutting down.
+/// Dropping the returned `Listening` blocks until the server stops.
+pub fn serve<F>(config :&Webhook, handler :F) -> Result<Listening, hyper::Error>
+    where F: Fn(Update) -> bool + Send + Sync + 'static {
+  let path = format!("/{}", config.secret);
+  let handle = move |mut req :Request, mut res :Response| {
+    let status = answer(&path, &mut req, &handler);
+    *res.status_mut() = status;
+    if let Err(e) = res.send(b"") {
+      warn!("Unable to answer the webhook request: {}", e);
+    }
+  };
+
+  match config.tls {
+    Some((ref cert, ref key)) => {
+      let ssl = try!(Openssl::with_cert_and_key(cert, key).map_err(|e| hyper::Error::Ssl(Box::new(e))));
+      try!(Server::https(&*config.listen, ssl)).handle(handle)
+    },
+    None => try!(Server::http(&*config.listen)).handle(handle),
+  }
+}
+
+fn answer<F>(path :&str, req :&mut Request, handler :&F) -> StatusCode where F: Fn(Update) -> bool {
+  match req.uri {
+    RequestUri::AbsolutePath(ref p) if p == path => (),
+    _ => {
+      warn!("Webhook request for an unknown path from {}", req.remote_addr);
+      return StatusCode::NotFound;
+    },
+  }
+  if req.method != Method::Post {
+    return StatusCode::MethodNotAllowed;
+  }
+
+  let mut body = String::new();
+  if let Err(e) = req.read_to_string(&mut body) {
+    warn!("Unable to read the webhook request: {}", e);
+    return StatusCode::BadRequest;
+  }
+
+  match json::decode::<Update>(&body) {
+    Ok(update) => if handler(update) {
+      StatusCode::Ok
+    } else {
+      StatusCode::ServiceUnavailable
+    },
+    Err(e) => {
+      warn!("Unable to decode the update {:?}: {:?}", body, e);
+      // Telegram would deliver it again and again
+      StatusCode::Ok
+    },
+  }
+}
+
+
+
+#[cfg(test)]
+mod test {
+  use super::serve;
+  use config::Webhook;
+  use hyper::server::Listening;
+  use std::io::prelude::*;
+  use std::net::TcpStream;
+
+  fn config() -> Webhook {
+    Webhook{
+      url: "https://bot.example.com/".into(),
+      listen: "127.0.0.1:0".into(),
+      secret: "s3cret".into(),
+      tls: None,
+    }
+  }
+
+  /// Send a raw request and return the status line of the response
+  fn request(mut listening :Listening, request :&str) -> String {
+    let mut stream = TcpStream::connect(listening.socket).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    listening.close().unwrap();
+
+    response.lines().next().unwrap_or("").into()
+  }
+
+  #[test]
+  fn endpoint() {
+    assert_eq!("https://bot.example.com/s3cret", config().endpoint());
+  }
+
+  #[test]
+  fn unknown_path() {
+    let listening = serve(&config(), |_| panic!("no update expected")).unwrap();
+    let status = request(listening, "POST /guess HTTP/1.1\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}");
+    assert_eq!("HTTP/1.1 404 Not Found", status);
+  }
+
+  #[test]
+  fn method_not_allowed() {
+    let listening = serve(&config(), |_| panic!("no update expected")).unwrap();
+    let status = request(listening, "GET /s3cret HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert_eq!("HTTP/1.1 405 Method Not Allowed", status);
+  }
+
+  #[test]
+  fn refused_update() {
+    let listening = serve(&config(), |_| false).unwrap();
+    let body = r#"{"update_id":1}"#;
+    let status = request(listening, &format!("POST /s3cret HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body));
+    assert_eq!("HTTP/1.1 503 Service Unavailable", status);
+  }
+}